@@ -0,0 +1,400 @@
+use std::{path::PathBuf, time::Duration};
+
+use log::LevelFilter;
+
+use crate::http::limits::Limits;
+use thiserror::Error;
+
+pub const USAGE: &str = "\
+Usage: codecrafters-http-server [OPTIONS]
+
+Options:
+      --bind <ADDR>         Address to listen on [default: 127.0.0.1]
+  -p, --port <PORT>         Port to listen on [default: 4221]
+  -d, --directory <DIR>     Serve and store files under /files from DIR
+      --mime-types <FILE>   Extra extension mappings, in mime.types format
+      --sniff-types         Guess the type of files without a known extension
+                            from their contents, instead of serving them as
+                            application/octet-stream
+  -w, --workers <N>         Number of worker threads [default: 32]
+      --queue-depth <N>     Connections that may wait for a busy worker [default: 128]
+      --shutdown-timeout <SECS>
+                            Time open connections get to finish on shutdown [default: 30]
+      --keep-alive-timeout <SECS>
+                            Time an idle connection is kept open [default: 5]
+      --max-requests <N>    Requests served on one connection before closing it [default: 100]
+      --header-timeout <SECS>
+                            Time a client gets to send a request head [default: 10]
+      --body-timeout <SECS>
+                            Time a request body may stall between reads [default: 30]
+      --write-timeout <SECS>
+                            Time a response may stall between writes [default: 30]
+      --max-header-size <BYTES>
+                            Largest request header section [default: 65536]
+      --max-body-size <BYTES>
+                            Largest request body [default: 67108864]
+      --log-level <LEVEL>   off, error, warn, info, debug or trace [default: info]
+  -h, --help                Print this help and exit
+  -V, --version             Print the version and exit
+";
+
+/// Most connections `--queue-depth` lets wait for a worker.
+const MAX_QUEUE_DEPTH: usize = 65536;
+
+/// Longest timeout the timeout flags accept, in seconds.
+const MAX_TIMEOUT_SECS: u64 = 24 * 60 * 60;
+
+/// Server settings.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Host name or IP address to listen on.
+    pub bind: String,
+    pub port: u16,
+    /// Root of the `/files` routes, which are disabled without one.
+    pub directory: Option<PathBuf>,
+    /// A `mime.types` file extending the built-in extension mappings.
+    pub mime_types: Option<PathBuf>,
+    /// Guess the media type of files with no known extension from their
+    /// contents.
+    pub sniff_types: bool,
+    /// Number of threads serving connections.
+    pub workers: usize,
+    /// Accepted connections that may wait for a free worker before new ones
+    /// are turned away with 503 Service Unavailable. With zero, connections
+    /// are only accepted while a worker is idle.
+    pub queue_depth: usize,
+    /// How long open connections may take to finish once the server is asked
+    /// to shut down, before they are closed forcibly.
+    pub shutdown_timeout: Duration,
+    /// How long a connection may sit idle between requests.
+    pub keep_alive_timeout: Duration,
+    /// How many requests a connection may carry before the server closes it.
+    pub max_requests: usize,
+    /// How long a client may take to send a complete request head.
+    pub header_timeout: Duration,
+    /// How long reading a request body may stall.
+    pub body_timeout: Duration,
+    /// How long writing a response may stall.
+    pub write_timeout: Duration,
+    pub limits: Limits,
+    pub log_level: LevelFilter,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1".to_string(),
+            port: 4221,
+            directory: None,
+            mime_types: None,
+            sniff_types: false,
+            workers: 32,
+            queue_depth: 128,
+            shutdown_timeout: Duration::from_secs(30),
+            keep_alive_timeout: Duration::from_secs(5),
+            max_requests: 100,
+            header_timeout: Duration::from_secs(10),
+            body_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(30),
+            limits: Limits::default(),
+            log_level: LevelFilter::Info,
+        }
+    }
+}
+
+/// What the command line asks for.
+#[derive(Debug)]
+pub enum Command {
+    Serve(Box<Config>),
+    Help,
+    Version,
+}
+
+/// An invalid command line.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("unexpected argument {0:?}")]
+    UnknownArgument(String),
+    #[error("{0} requires a value")]
+    MissingValue(String),
+    #[error("invalid value {value:?} for {flag}: {reason}")]
+    InvalidValue {
+        flag: String,
+        value: String,
+        reason: String,
+    },
+}
+
+impl Config {
+    /// Parses command-line arguments, not including the program name.
+    ///
+    /// Flags take their value either as the next argument or after `=`.
+    pub fn from_args<I>(args: I) -> Result<Command, ConfigError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| ConfigError::MissingValue(flag.clone()))
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => return Ok(Command::Help),
+                "-V" | "--version" => return Ok(Command::Version),
+                "--bind" => {
+                    let bind = value()?;
+                    if bind.is_empty() {
+                        return Err(invalid(&flag, bind, "must not be empty"));
+                    }
+                    config.bind = bind;
+                }
+                "-p" | "--port" => {
+                    let port = value()?;
+                    config.port = port
+                        .parse()
+                        .map_err(|_| invalid(&flag, port, "expected a number from 0 to 65535"))?;
+                }
+                "-d" | "--directory" => {
+                    let directory = PathBuf::from(value()?);
+                    if !directory.is_dir() {
+                        let shown = directory.display().to_string();
+                        return Err(invalid(&flag, shown, "not a directory"));
+                    }
+                    config.directory = Some(directory);
+                }
+                "--mime-types" => config.mime_types = Some(PathBuf::from(value()?)),
+                "--sniff-types" => {
+                    if let Some(value) = inline_value {
+                        return Err(invalid(&flag, value, "takes no value"));
+                    }
+                    config.sniff_types = true;
+                }
+                "-w" | "--workers" => {
+                    let workers = value()?;
+                    config.workers = match workers.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => return Err(invalid(&flag, workers, "expected a positive number")),
+                    };
+                }
+                "--queue-depth" => {
+                    let depth = value()?;
+                    config.queue_depth = match depth.parse() {
+                        Ok(n) if n <= MAX_QUEUE_DEPTH => n,
+                        _ => {
+                            let reason = format!("expected a number up to {}", MAX_QUEUE_DEPTH);
+                            return Err(invalid(&flag, depth, &reason));
+                        }
+                    };
+                }
+                "--shutdown-timeout" => config.shutdown_timeout = seconds(&flag, value()?)?,
+                "--keep-alive-timeout" => config.keep_alive_timeout = seconds(&flag, value()?)?,
+                "--max-requests" => {
+                    let max = value()?;
+                    config.max_requests = match max.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => return Err(invalid(&flag, max, "expected a positive number")),
+                    };
+                }
+                "--header-timeout" => config.header_timeout = seconds(&flag, value()?)?,
+                "--body-timeout" => config.body_timeout = seconds(&flag, value()?)?,
+                "--write-timeout" => config.write_timeout = seconds(&flag, value()?)?,
+                "--max-header-size" => {
+                    let size = value()?;
+                    config.limits.header_size = size
+                        .parse()
+                        .map_err(|_| invalid(&flag, size, "expected a number of bytes"))?;
+                }
+                "--max-body-size" => {
+                    let size = value()?;
+                    config.limits.body = size
+                        .parse()
+                        .map_err(|_| invalid(&flag, size, "expected a number of bytes"))?;
+                }
+                "--log-level" => {
+                    let level = value()?;
+                    config.log_level = level.parse().map_err(|_| {
+                        invalid(
+                            &flag,
+                            level,
+                            "expected off, error, warn, info, debug or trace",
+                        )
+                    })?;
+                }
+                _ => return Err(ConfigError::UnknownArgument(flag)),
+            }
+        }
+        Ok(Command::Serve(Box::new(config)))
+    }
+
+    /// The address to listen on, as accepted by `TcpListener::bind`.
+    pub fn addr(&self) -> (&str, u16) {
+        (&self.bind, self.port)
+    }
+}
+
+fn invalid(flag: &str, value: String, reason: &str) -> ConfigError {
+    ConfigError::InvalidValue {
+        flag: flag.to_string(),
+        value,
+        reason: reason.to_string(),
+    }
+}
+
+/// Parses a positive number of seconds, up to a day. Socket timeouts cannot
+/// be zero, and deadlines far in the future cannot be represented.
+fn seconds(flag: &str, value: String) -> Result<Duration, ConfigError> {
+    match value.parse() {
+        Ok(secs) if secs > 0 && secs <= MAX_TIMEOUT_SECS => Ok(Duration::from_secs(secs)),
+        _ => {
+            let reason = format!(
+                "expected a number of seconds from 1 to {}",
+                MAX_TIMEOUT_SECS
+            );
+            Err(invalid(flag, value, &reason))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, ConfigError> {
+        Config::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn serve(args: &[&str]) -> Config {
+        match parse(args) {
+            Ok(Command::Serve(config)) => *config,
+            other => panic!("{args:?}: {other:?}"),
+        }
+    }
+
+    fn invalid_flag(args: &[&str]) -> String {
+        match parse(args) {
+            Err(ConfigError::InvalidValue { flag, .. }) => flag,
+            other => panic!("{args:?}: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn defaults_without_arguments() {
+        let config = serve(&[]);
+        assert_eq!(config.addr(), ("127.0.0.1", 4221));
+        assert_eq!(config.workers, 32);
+        assert_eq!(config.max_requests, 100);
+        assert!(config.directory.is_none());
+        assert!(!config.sniff_types);
+    }
+
+    #[test]
+    fn takes_values_after_equals_or_as_next_argument() {
+        let separate = serve(&["--port", "8080", "--workers", "4", "--log-level", "debug"]);
+        let inline = serve(&["--port=8080", "--workers=4", "--log-level=debug"]);
+        for config in [separate, inline] {
+            assert_eq!(config.port, 8080);
+            assert_eq!(config.workers, 4);
+            assert_eq!(config.log_level, LevelFilter::Debug);
+        }
+        assert_eq!(serve(&["-p", "80", "-w", "2"]).port, 80);
+        assert_eq!(serve(&["--bind=a=b"]).bind, "a=b");
+        assert_eq!(
+            serve(&["--keep-alive-timeout=7"]).keep_alive_timeout,
+            Duration::from_secs(7)
+        );
+    }
+
+    #[test]
+    fn later_flags_override_earlier_ones() {
+        assert_eq!(serve(&["--port=1", "-p", "2"]).port, 2);
+    }
+
+    #[test]
+    fn parses_switches() {
+        assert!(serve(&["--sniff-types"]).sniff_types);
+        assert_eq!(invalid_flag(&["--sniff-types=yes"]), "--sniff-types");
+    }
+
+    #[test]
+    fn parses_directories() {
+        let dir = std::env::temp_dir();
+        let config = serve(&["-d", dir.to_str().unwrap()]);
+        assert_eq!(config.directory, Some(dir));
+        assert_eq!(
+            invalid_flag(&["--directory", "/nonexistent/directory"]),
+            "--directory"
+        );
+    }
+
+    #[test]
+    fn reports_missing_values() {
+        for (args, missing) in [
+            (&["--port"][..], "--port"),
+            (&["-d"], "-d"),
+            (&["--workers", "4", "--log-level"], "--log-level"),
+        ] {
+            match parse(args) {
+                Err(ConfigError::MissingValue(flag)) => assert_eq!(flag, missing),
+                other => panic!("{args:?}: {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for (args, flag) in [
+            (&["--port", "65536"][..], "--port"),
+            (&["--port=http"], "--port"),
+            (&["-p", "-1"], "-p"),
+            (&["--bind="], "--bind"),
+            (&["--workers=0"], "--workers"),
+            (&["--queue-depth=65537"], "--queue-depth"),
+            (&["--max-requests=0"], "--max-requests"),
+            (&["--keep-alive-timeout=0"], "--keep-alive-timeout"),
+            (&["--header-timeout=86401"], "--header-timeout"),
+            (&["--max-body-size=lots"], "--max-body-size"),
+            (&["--log-level=loud"], "--log-level"),
+        ] {
+            assert_eq!(invalid_flag(args), flag, "{args:?}");
+        }
+        assert_eq!(serve(&["--queue-depth=0"]).queue_depth, 0);
+        assert_eq!(serve(&["--port=0"]).port, 0);
+    }
+
+    #[test]
+    fn help_and_version_short_circuit() {
+        assert!(matches!(parse(&["-h"]), Ok(Command::Help)));
+        assert!(matches!(parse(&["--help", "--bogus"]), Ok(Command::Help)));
+        assert!(matches!(parse(&["-V"]), Ok(Command::Version)));
+        assert!(matches!(
+            parse(&["--version", "--port"]),
+            Ok(Command::Version)
+        ));
+        assert!(matches!(
+            parse(&["--bogus", "--help"]),
+            Err(ConfigError::UnknownArgument(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_arguments() {
+        for arg in ["--bogus", "-x", "serve", "-p=80", "--port8080"] {
+            match parse(&[arg]) {
+                Err(ConfigError::UnknownArgument(unknown)) => assert_eq!(unknown, arg),
+                other => panic!("{arg:?}: {other:?}"),
+            }
+        }
+    }
+}
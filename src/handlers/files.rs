@@ -0,0 +1,624 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap, VecDeque},
+    fs::{self, File, Metadata, OpenOptions},
+    hash::{BuildHasher, Hasher},
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, error, warn};
+
+use crate::{
+    handlers::{
+        listing,
+        resolve::{self, PathError},
+    },
+    http::{
+        body::Body,
+        conditional::{self, EntityTag, Precondition, Validators},
+        digest,
+        header::HeaderMap,
+        mime::{self, MimeTypes},
+        range::{self, ByteRanges},
+        request::Request,
+        response::{Parts, Response},
+        status::StatusCode,
+    },
+};
+
+/// Where the `/files` routes read and write files.
+///
+/// Registered as router state; the file handlers answer 404 without it.
+/// Uploads are bounded by the request body limit, `--max-body-size`, which
+/// is enforced before their bodies are read.
+pub struct FilesConfig {
+    pub directory: PathBuf,
+    /// Media types of served files, by extension.
+    pub mime_types: MimeTypes,
+    /// Guess the media type of files with no known extension from their
+    /// first bytes, instead of labelling them `application/octet-stream`.
+    ///
+    /// Off by default: anyone who can upload a file could otherwise have it
+    /// served as HTML.
+    pub sniff: bool,
+    /// File served for a directory that contains it.
+    pub index: Option<String>,
+    /// List the entries of directories that have no index file, instead of
+    /// answering 404.
+    pub listing: bool,
+    /// Offer listings as JSON to clients that ask for `application/json`.
+    pub json_listing: bool,
+}
+
+impl FilesConfig {
+    pub fn new(directory: PathBuf) -> Self {
+        Self {
+            directory,
+            mime_types: MimeTypes::default(),
+            sniff: false,
+            index: Some("index.html".to_string()),
+            listing: true,
+            json_listing: true,
+        }
+    }
+
+    /// Determines the `Content-Type` of the file at `path`, leaving `file`
+    /// positioned at its start.
+    fn content_type(&self, path: &Path, file: &mut File) -> io::Result<String> {
+        let mime_type = match self.mime_types.lookup(path) {
+            Some(mime_type) => mime_type,
+            None if self.sniff => {
+                let mut head = Vec::with_capacity(mime::SNIFF_LEN);
+                Read::by_ref(file)
+                    .take(mime::SNIFF_LEN as u64)
+                    .read_to_end(&mut head)?;
+                file.rewind()?;
+                mime::sniff(&head)
+            }
+            None => "application/octet-stream",
+        };
+        Ok(mime::with_charset(mime_type))
+    }
+}
+
+pub fn files_handler(req: &Request, params: HashMap<String, String>) -> Response {
+    let Some(config) = req.state.get::<FilesConfig>() else {
+        warn!("Server must be started with --directory <dir>");
+        return Response::new(Parts::new(StatusCode::NOT_FOUND, req.head.version), None);
+    };
+
+    let raw_path = params.get("path").map(String::as_str).unwrap_or_default();
+    let full_path = match resolve::resolve(&config.directory, raw_path) {
+        Ok(path) => path,
+        Err(e) => return path_error(req, e),
+    };
+    if full_path.is_dir() {
+        return serve_directory(req, config, &full_path, raw_path.is_empty());
+    }
+    serve_file(req, config, &full_path)
+}
+
+/// Answers a request for a directory with its index file, if it has one, or
+/// else with a listing of its entries.
+fn serve_directory(req: &Request, config: &FilesConfig, dir: &Path, is_root: bool) -> Response {
+    let (url_path, query) = match req.head.path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (req.head.path.as_str(), None),
+    };
+    // Relative links in the index file or listing resolve against the
+    // directory only if its URL ends with a slash.
+    if !url_path.ends_with('/') {
+        let mut location = format!("{}/", url_path);
+        if let Some(query) = query {
+            location.push('?');
+            location.push_str(query);
+        }
+        let mut head = Parts::new(StatusCode::MOVED_PERMANENTLY, req.head.version);
+        head.headers.insert("Location", location);
+        return Response::new(head, None);
+    }
+
+    if let Some(index) = &config.index {
+        let index_path = dir.join(index);
+        if index_path.is_file() {
+            // The index file may be a symlink, which must not escape either.
+            return match resolve::contain(&config.directory, &index_path) {
+                Ok(index_path) => serve_file(req, config, &index_path),
+                Err(e) => e.response(req.head.version),
+            };
+        }
+    }
+    if !config.listing {
+        return Response::new(Parts::new(StatusCode::NOT_FOUND, req.head.version), None);
+    }
+
+    let entries = match listing::read_entries(&config.directory, dir) {
+        Ok(entries) => entries,
+        Err(_) => {
+            error!("Failed to read directory {}", dir.display());
+            return Response::new(
+                Parts::new(StatusCode::INTERNAL_SERVER_ERROR, req.head.version),
+                None,
+            );
+        }
+    };
+
+    let mut head = Parts::new(StatusCode::OK, req.head.version);
+    let body = if config.json_listing && prefers_json(&req.head.headers) {
+        head.headers.insert("Content-Type", "application/json");
+        listing::render_json(&entries)
+    } else {
+        head.headers
+            .insert("Content-Type", "text/html; charset=utf-8");
+        listing::render_html(url_path, &entries, is_root)
+    };
+    if config.json_listing {
+        head.headers.insert("Vary", "Accept");
+    }
+    head.headers.insert("X-Content-Type-Options", "nosniff");
+    Response::new(head, body.into_bytes())
+}
+
+/// Whether the client asked for JSON rather than HTML. Only the presence of
+/// the media types in `Accept` is considered, not their q-values.
+fn prefers_json(headers: &HeaderMap) -> bool {
+    let accept = headers.get_all("Accept").collect::<Vec<_>>().join(",");
+    let mut media_types = accept
+        .split(',')
+        .map(|item| item.split(';').next().unwrap_or_default().trim());
+    let accepts_json = media_types
+        .clone()
+        .any(|m| m.eq_ignore_ascii_case("application/json"));
+    accepts_json && !media_types.any(|m| m.eq_ignore_ascii_case("text/html"))
+}
+
+fn serve_file(req: &Request, config: &FilesConfig, full_path: &Path) -> Response {
+    let (mut file, metadata) = match File::open(full_path).and_then(|f| {
+        let metadata = f.metadata()?;
+        Ok((f, metadata))
+    }) {
+        Ok(opened) => opened,
+        Err(e) => {
+            debug!("Failed to open {}: {}", full_path.display(), e);
+            return Response::new(Parts::new(StatusCode::NOT_FOUND, req.head.version), None);
+        }
+    };
+    let len = metadata.len();
+    let validators = validators(&metadata);
+
+    match conditional::evaluate(&req.head.headers, &req.head.method, Some(&validators)) {
+        Precondition::Proceed => {}
+        Precondition::NotModified => {
+            let mut head = Parts::new(StatusCode::NOT_MODIFIED, req.head.version);
+            validators.write_headers(&mut head.headers);
+            return Response::new(head, None);
+        }
+        Precondition::Failed => {
+            return Response::new(
+                Parts::new(StatusCode::PRECONDITION_FAILED, req.head.version),
+                None,
+            );
+        }
+    }
+
+    let content_type = match config.content_type(full_path, &mut file) {
+        Ok(content_type) => content_type,
+        Err(e) => {
+            error!("Failed to read {}: {}", full_path.display(), e);
+            return Response::new(
+                Parts::new(StatusCode::INTERNAL_SERVER_ERROR, req.head.version),
+                None,
+            );
+        }
+    };
+
+    let ranges = match req.head.headers.get("Range") {
+        Some(range) if validators.if_range_matches(&req.head.headers) => range::parse(range, len),
+        _ => ByteRanges::Full,
+    };
+
+    let mut response = match ranges {
+        ByteRanges::Full => {
+            let mut head = Parts::new(StatusCode::OK, req.head.version);
+            head.headers.insert("Content-Type", content_type.as_str());
+            Response::new(head, Body::from_reader(file, Some(len)))
+        }
+        ByteRanges::Unsatisfiable => {
+            let mut head = Parts::new(StatusCode::RANGE_NOT_SATISFIABLE, req.head.version);
+            head.headers
+                .insert("Content-Range", format!("bytes */{}", len));
+            Response::new(head, None)
+        }
+        ByteRanges::Partial(ranges) => match partial_content(file, &ranges, len, &content_type) {
+            Ok((content_type, body)) => {
+                let mut head = Parts::new(StatusCode::PARTIAL_CONTENT, req.head.version);
+                head.headers.insert("Content-Type", content_type);
+                if let [range] = ranges.as_slice() {
+                    head.headers
+                        .insert("Content-Range", range::content_range(range, len));
+                }
+                Response::new(head, body)
+            }
+            Err(e) => {
+                error!("Failed to read {}: {}", full_path.display(), e);
+                return Response::new(
+                    Parts::new(StatusCode::INTERNAL_SERVER_ERROR, req.head.version),
+                    None,
+                );
+            }
+        },
+    };
+
+    response.head.headers.insert("Accept-Ranges", "bytes");
+    // The declared type is authoritative; browsers must not second-guess it.
+    response
+        .head
+        .headers
+        .insert("X-Content-Type-Options", "nosniff");
+    validators.write_headers(&mut response.head.headers);
+    response
+}
+
+/// Builds the body of a 206 response, returning it with its content type.
+///
+/// A single range is sent as is; several are sent as `multipart/byteranges`
+/// (RFC 9110 section 14.6).
+fn partial_content(
+    mut file: File,
+    ranges: &[Range<u64>],
+    len: u64,
+    content_type: &str,
+) -> io::Result<(String, Body)> {
+    if let [range] = ranges {
+        file.seek(SeekFrom::Start(range.start))?;
+        let body = Body::from_reader(
+            file.take(range.end - range.start),
+            Some(range.end - range.start),
+        );
+        return Ok((content_type.to_string(), body));
+    }
+
+    let boundary = format!("{:016x}", RandomState::new().build_hasher().finish());
+    let mut segments = VecDeque::new();
+    for range in ranges {
+        let part_head = format!(
+            "--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+            boundary,
+            content_type,
+            range::content_range(range, len)
+        );
+        segments.push_back(Segment::Bytes(Cursor::new(part_head.into_bytes())));
+        segments.push_back(Segment::File(range.clone()));
+        segments.push_back(Segment::Bytes(Cursor::new(b"\r\n".to_vec())));
+    }
+    segments.push_back(Segment::Bytes(Cursor::new(
+        format!("--{}--\r\n", boundary).into_bytes(),
+    )));
+
+    let body_len = segments.iter().map(Segment::len).sum();
+    let reader = MultipartReader { file, segments };
+    Ok((
+        format!("multipart/byteranges; boundary={}", boundary),
+        Body::from_reader(reader, Some(body_len)),
+    ))
+}
+
+/// Streams a `multipart/byteranges` body, reading each range from the file
+/// only when it is reached.
+struct MultipartReader {
+    file: File,
+    segments: VecDeque<Segment>,
+}
+
+enum Segment {
+    Bytes(Cursor<Vec<u8>>),
+    File(Range<u64>),
+}
+
+impl Segment {
+    fn len(&self) -> u64 {
+        match self {
+            Segment::Bytes(bytes) => bytes.get_ref().len() as u64,
+            Segment::File(range) => range.end - range.start,
+        }
+    }
+}
+
+impl Read for MultipartReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(segment) = self.segments.front_mut() {
+            let n = match segment {
+                Segment::Bytes(bytes) => bytes.read(buf)?,
+                Segment::File(range) if range.start < range.end => {
+                    let max = buf.len().min((range.end - range.start) as usize);
+                    self.file.seek(SeekFrom::Start(range.start))?;
+                    let n = self.file.read(&mut buf[..max])?;
+                    if n == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "file shrank while it was being sent",
+                        ));
+                    }
+                    range.start += n as u64;
+                    n
+                }
+                Segment::File(_) => 0,
+            };
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            self.segments.pop_front();
+        }
+        Ok(0)
+    }
+}
+
+/// Derives validators from file metadata. The entity tag combines size and
+/// modification time; it is weak for a file modified within the last second,
+/// since it could change again without its timestamp moving.
+fn validators(metadata: &Metadata) -> Validators {
+    let last_modified = metadata.modified().ok();
+    let etag = last_modified.and_then(|modified| {
+        let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+        let tag = format!(
+            "{:x}-{:x}.{:x}",
+            metadata.len(),
+            since_epoch.as_secs(),
+            since_epoch.subsec_nanos()
+        );
+        let settled = SystemTime::now()
+            .duration_since(modified)
+            .is_ok_and(|age| age >= Duration::from_secs(1));
+        Some(if settled {
+            EntityTag::strong(tag)
+        } else {
+            EntityTag::weak(tag)
+        })
+    });
+
+    Validators {
+        etag,
+        last_modified,
+    }
+}
+
+pub fn post_file_handler(req: &Request, params: HashMap<String, String>) -> Response {
+    match upload(req, &params) {
+        Ok(_) => Response::new(Parts::new(StatusCode::CREATED, req.head.version), None),
+        Err(response) => response,
+    }
+}
+
+/// Creates or replaces a file, answering 201 if it was created and 204 if it
+/// was replaced.
+pub fn put_file_handler(req: &Request, params: HashMap<String, String>) -> Response {
+    match upload(req, &params) {
+        Ok(true) => Response::new(Parts::new(StatusCode::CREATED, req.head.version), None),
+        Ok(false) => Response::new(Parts::new(StatusCode::NO_CONTENT, req.head.version), None),
+        Err(response) => response,
+    }
+}
+
+pub fn delete_file_handler(req: &Request, params: HashMap<String, String>) -> Response {
+    delete_file(req, &params).unwrap_or_else(|response| response)
+}
+
+/// Updates part of an existing file: the body is written at the range given
+/// by `Content-Range`, or appended to the file if there is none.
+pub fn patch_file_handler(req: &Request, params: HashMap<String, String>) -> Response {
+    patch_file(req, &params).unwrap_or_else(|response| response)
+}
+
+/// Stores the request body in the file named by the `path` parameter,
+/// returning whether the file was created.
+fn upload(req: &Request, params: &HashMap<String, String>) -> Result<bool, Response> {
+    let config = files_config(req)?;
+    check_body(req)?;
+
+    let raw_path = params.get("path").map(String::as_str).unwrap_or_default();
+    let file_path =
+        resolve::resolve_for_write(&config.directory, raw_path).map_err(|e| path_error(req, e))?;
+    if file_path.is_dir() {
+        return Err(Response::new(
+            Parts::new(StatusCode::FORBIDDEN, req.head.version),
+            None,
+        ));
+    }
+
+    let existing = fs::metadata(&file_path).ok().map(|m| validators(&m));
+    check_preconditions(req, existing.as_ref())?;
+
+    // The file may have been created since it was checked, so `If-None-Match:
+    // *` is enforced again when the upload is put in place.
+    let overwrite = req
+        .head
+        .headers
+        .get("If-None-Match")
+        .map_or(true, |value| value.trim() != "*");
+    match write_atomically(&file_path, &req.body, overwrite) {
+        Ok(()) => Ok(existing.is_none()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(Response::new(
+            Parts::new(StatusCode::PRECONDITION_FAILED, req.head.version),
+            None,
+        )),
+        Err(e) => {
+            error!("Failed to write file: {}", e);
+            Err(Response::new(
+                Parts::new(StatusCode::INTERNAL_SERVER_ERROR, req.head.version),
+                None,
+            ))
+        }
+    }
+}
+
+fn delete_file(req: &Request, params: &HashMap<String, String>) -> Result<Response, Response> {
+    let config = files_config(req)?;
+    let raw_path = params.get("path").map(String::as_str).unwrap_or_default();
+    // A symlink is deleted itself, not its target.
+    let file_path =
+        resolve::resolve_entry(&config.directory, raw_path).map_err(|e| path_error(req, e))?;
+
+    let existing = match fs::symlink_metadata(&file_path) {
+        Ok(metadata) if metadata.is_dir() => {
+            return Err(Response::new(
+                Parts::new(StatusCode::FORBIDDEN, req.head.version),
+                None,
+            ));
+        }
+        Ok(metadata) => validators(&metadata),
+        Err(e) => return Err(path_error(req, e.into())),
+    };
+    check_preconditions(req, Some(&existing))?;
+
+    fs::remove_file(&file_path).map_err(|e| path_error(req, e.into()))?;
+    Ok(Response::new(
+        Parts::new(StatusCode::NO_CONTENT, req.head.version),
+        None,
+    ))
+}
+
+fn patch_file(req: &Request, params: &HashMap<String, String>) -> Result<Response, Response> {
+    let config = files_config(req)?;
+    check_body(req)?;
+
+    let raw_path = params.get("path").map(String::as_str).unwrap_or_default();
+    let file_path =
+        resolve::resolve(&config.directory, raw_path).map_err(|e| path_error(req, e))?;
+    let metadata = fs::metadata(&file_path).map_err(|e| path_error(req, e.into()))?;
+    if metadata.is_dir() {
+        return Err(Response::new(
+            Parts::new(StatusCode::FORBIDDEN, req.head.version),
+            None,
+        ));
+    }
+    check_preconditions(req, Some(&validators(&metadata)))?;
+
+    let range = match req.head.headers.get("Content-Range") {
+        Some(value) => match range::parse_content_range(value) {
+            Some(range) if range.end - range.start == req.body.len() as u64 => Some(range),
+            _ => {
+                return Err(Response::new(
+                    Parts::new(StatusCode::BAD_REQUEST, req.head.version),
+                    None,
+                ));
+            }
+        },
+        None => None,
+    };
+    // Updates may extend the file, but not leave a hole in it.
+    if let Some(range) = &range {
+        if range.start > metadata.len() {
+            let mut head = Parts::new(StatusCode::RANGE_NOT_SATISFIABLE, req.head.version);
+            head.headers
+                .insert("Content-Range", format!("bytes */{}", metadata.len()));
+            return Err(Response::new(head, None));
+        }
+    }
+
+    let written = OpenOptions::new()
+        .write(true)
+        .append(range.is_none())
+        .open(&file_path)
+        .and_then(|mut file| {
+            if let Some(range) = &range {
+                file.seek(SeekFrom::Start(range.start))?;
+            }
+            file.write_all(&req.body)?;
+            file.sync_all()?;
+            file.metadata()
+        });
+    match written {
+        Ok(metadata) => {
+            let mut head = Parts::new(StatusCode::NO_CONTENT, req.head.version);
+            validators(&metadata).write_headers(&mut head.headers);
+            Ok(Response::new(head, None))
+        }
+        Err(e) => {
+            error!("Failed to write file: {}", e);
+            Err(Response::new(
+                Parts::new(StatusCode::INTERNAL_SERVER_ERROR, req.head.version),
+                None,
+            ))
+        }
+    }
+}
+
+fn files_config(req: &Request) -> Result<&FilesConfig, Response> {
+    req.state.get::<FilesConfig>().ok_or_else(|| {
+        warn!("Server must be started with --directory <dir>");
+        Response::new(Parts::new(StatusCode::NOT_FOUND, req.head.version), None)
+    })
+}
+
+fn path_error(req: &Request, e: PathError) -> Response {
+    match &e {
+        PathError::Io(_) => debug!("Failed to resolve {}: {}", req.head.path, e),
+        _ => warn!("Refused {}: {}", req.head.path, e),
+    }
+    e.response(req.head.version)
+}
+
+/// Rejects request bodies that do not match their digests. Their size has
+/// already been checked against the request limits, before they were read.
+fn check_body(req: &Request) -> Result<(), Response> {
+    if let Err(e) = digest::verify(&req.head.headers, &req.body) {
+        warn!("Rejected upload: {}", e);
+        return Err(Response::new(
+            Parts::new(StatusCode::BAD_REQUEST, req.head.version),
+            None,
+        ));
+    }
+    Ok(())
+}
+
+/// Evaluates the preconditions of a request that modifies a file, given the
+/// validators of the file if it exists.
+fn check_preconditions(req: &Request, existing: Option<&Validators>) -> Result<(), Response> {
+    match conditional::evaluate(&req.head.headers, &req.head.method, existing) {
+        Precondition::Proceed => Ok(()),
+        _ => Err(Response::new(
+            Parts::new(StatusCode::PRECONDITION_FAILED, req.head.version),
+            None,
+        )),
+    }
+}
+
+/// Writes `data` to `path` through a temporary file in the same directory,
+/// so that readers see either the old contents or the new ones in full.
+///
+/// Unless `overwrite` is set, fails with `AlreadyExists` if `path` exists.
+fn write_atomically(path: &Path, data: &[u8], overwrite: bool) -> io::Result<()> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a file path",
+        ));
+    };
+    let temp_path = dir.join(format!(
+        "{}{}.{:016x}.tmp",
+        resolve::UPLOAD_PREFIX,
+        name.to_string_lossy(),
+        RandomState::new().build_hasher().finish()
+    ));
+
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()?;
+            if overwrite {
+                fs::rename(&temp_path, path)
+            } else {
+                // Unlike a rename, linking never replaces an existing file.
+                fs::hard_link(&temp_path, path)
+            }
+        });
+    if result.is_err() || !overwrite {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
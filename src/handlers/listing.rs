@@ -0,0 +1,133 @@
+use std::{fs, io, path::Path, time::SystemTime};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde_json::json;
+
+use crate::{handlers::resolve, http::date};
+
+/// Characters escaped in the links of a listing: everything but RFC 3986
+/// unreserved characters.
+const LINK: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// An entry of a directory listing.
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl Entry {
+    fn href(&self) -> String {
+        let mut href = utf8_percent_encode(&self.name, LINK).to_string();
+        if self.is_dir {
+            href.push('/');
+        }
+        href
+    }
+}
+
+/// Reads the entries of `dir`, a directory under `root`, directories first
+/// and then by name. Entries whose names are not valid UTF-8, temporary upload
+/// files, and symlinks leading outside `root` are left out.
+pub fn read_entries(root: &Path, dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if resolve::is_reserved(&name) {
+            continue;
+        }
+        // Follow symlinks, so that a link to a directory is listed as one, but
+        // only those the resolver would follow too.
+        let is_symlink = entry.file_type().is_ok_and(|t| t.is_symlink());
+        if is_symlink && resolve::contain(root, &entry.path()).is_err() {
+            continue;
+        }
+        let Ok(metadata) = fs::metadata(entry.path()) else {
+            continue;
+        };
+        entries.push(Entry {
+            name,
+            is_dir: metadata.is_dir(),
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+            modified: metadata.modified().ok(),
+        });
+    }
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+/// Renders an HTML listing of the directory at `url_path`, with a link to the
+/// parent directory unless it is the root of the served tree.
+pub fn render_html(url_path: &str, entries: &[Entry], is_root: bool) -> String {
+    let title = format!("Index of {}", escape_html(url_path));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n\
+         <table>\n<tr><th>Name</th><th>Size</th><th>Last modified</th></tr>\n",
+    );
+    if !is_root {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        let name = if entry.is_dir {
+            format!("{}/", entry.name)
+        } else {
+            entry.name.clone()
+        };
+        let size = if entry.is_dir {
+            "-".to_string()
+        } else {
+            entry.size.to_string()
+        };
+        let modified = entry.modified.map(date::format).unwrap_or_default();
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td></tr>\n",
+            escape_html(&entry.href()),
+            escape_html(&name),
+            size,
+            modified
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// Renders a JSON listing: an array of objects with `name`, `type`
+/// (`"file"` or `"directory"`), `size` and `modified` fields.
+pub fn render_json(entries: &[Entry]) -> String {
+    let entries = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "name": entry.name,
+                "type": if entry.is_dir { "directory" } else { "file" },
+                "size": entry.size,
+                "modified": entry.modified.map(date::format),
+            })
+        })
+        .collect::<Vec<_>>();
+    serde_json::Value::Array(entries).to_string()
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,48 @@
+mod files;
+mod listing;
+mod resolve;
+
+use std::collections::HashMap;
+
+use log::debug;
+
+use crate::http::{
+    request::Request,
+    response::{Parts, Response},
+    status::StatusCode,
+};
+
+pub use files::{
+    delete_file_handler, files_handler, patch_file_handler, post_file_handler, put_file_handler,
+    FilesConfig,
+};
+
+pub fn root_handler(req: &Request, _params: HashMap<String, String>) -> Response {
+    let head = Parts::new(StatusCode::OK, req.head.version);
+
+    Response::new(head, None)
+}
+
+pub fn echo_handler(req: &Request, params: HashMap<String, String>) -> Response {
+    let mut head = Parts::new(StatusCode::OK, req.head.version);
+    head.headers.insert("Content-Type", "text/plain");
+
+    let echo_part = params.get("msg").unwrap_or(&"".to_string()).clone();
+    debug!("Echoing message: {}", echo_part);
+
+    Response::new(head, Some(echo_part.into_bytes()))
+}
+
+pub fn user_agent_handler(req: &Request, _params: HashMap<String, String>) -> Response {
+    let mut head = Parts::new(StatusCode::OK, req.head.version);
+
+    let user_agent = req
+        .head
+        .headers
+        .get("User-Agent")
+        .unwrap_or_default()
+        .to_string();
+    head.headers.insert("Content-Type", "text/plain");
+
+    Response::new(head, Some(user_agent.into_bytes()))
+}
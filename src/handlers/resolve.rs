@@ -0,0 +1,356 @@
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use percent_encoding::percent_decode_str;
+use thiserror::Error;
+
+use crate::http::{
+    response::{Parts, Response},
+    status::StatusCode,
+    version::Version,
+};
+
+/// Prefix of the temporary files uploads are written to. Paths naming them
+/// do not resolve, so that incomplete uploads cannot be read.
+pub const UPLOAD_PREFIX: &str = ".upload~";
+
+/// Whether `name` is reserved for a temporary upload file.
+pub fn is_reserved(name: &str) -> bool {
+    name.starts_with(UPLOAD_PREFIX)
+}
+
+/// Why a request path could not be mapped to a file under the served
+/// directory.
+#[derive(Debug, Error)]
+pub enum PathError {
+    #[error("malformed path {0:?}")]
+    Malformed(String),
+    #[error("path {0:?} is outside the served directory")]
+    Forbidden(String),
+    #[error("path {0:?} names a temporary upload file")]
+    Reserved(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl PathError {
+    /// The response for a request whose path failed to resolve.
+    pub fn response(&self, version: Version) -> Response {
+        let status = match self {
+            PathError::Malformed(_) => StatusCode::BAD_REQUEST,
+            PathError::Forbidden(_) => StatusCode::FORBIDDEN,
+            PathError::Reserved(_) => StatusCode::NOT_FOUND,
+            PathError::Io(e) if e.kind() == io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            PathError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Response::new(Parts::new(status, version), None)
+    }
+}
+
+/// Maps the percent-encoded path `raw`, relative to `root`, to the canonical
+/// path of an existing file or directory under `root`.
+///
+/// Symlinks are followed, and must lead to somewhere under `root` as well.
+pub fn resolve(root: &Path, raw: &str) -> Result<PathBuf, PathError> {
+    let relative = decode(raw)?;
+    contain(root, &root.join(relative))
+}
+
+/// Maps `raw` to a path under `root` without resolving its last component,
+/// so that a symlink is named itself rather than its target. Only the parent
+/// directory has to exist.
+pub fn resolve_entry(root: &Path, raw: &str) -> Result<PathBuf, PathError> {
+    let relative = decode(raw)?;
+    let (Some(parent), Some(name)) = (relative.parent(), relative.file_name()) else {
+        return Err(PathError::Malformed(raw.to_string()));
+    };
+    Ok(contain(root, &root.join(parent))?.join(name))
+}
+
+/// Like [`resolve`], but for a file that is about to be written and may not
+/// exist yet.
+pub fn resolve_for_write(root: &Path, raw: &str) -> Result<PathBuf, PathError> {
+    let entry = resolve_entry(root, raw)?;
+    // Writing through a symlink writes to wherever it points.
+    if entry
+        .symlink_metadata()
+        .is_ok_and(|metadata| metadata.file_type().is_symlink())
+    {
+        return contain(root, &entry);
+    }
+    Ok(entry)
+}
+
+/// Canonicalizes `path`, checking that it is `root` or below it.
+pub fn contain(root: &Path, path: &Path) -> Result<PathBuf, PathError> {
+    let canonical = path.canonicalize()?;
+    if canonical.starts_with(root.canonicalize()?) {
+        Ok(canonical)
+    } else {
+        Err(PathError::Forbidden(path.display().to_string()))
+    }
+}
+
+/// Percent-decodes `raw` into a relative path without `..` components or
+/// reserved names.
+fn decode(raw: &str) -> Result<PathBuf, PathError> {
+    let decoded = percent_decode_str(raw)
+        .decode_utf8()
+        .map_err(|_| PathError::Malformed(raw.to_string()))?;
+    if decoded.contains('\0') {
+        return Err(PathError::Malformed(raw.to_string()));
+    }
+
+    let path = PathBuf::from(decoded.as_ref());
+    for component in path.components() {
+        match component {
+            Component::Normal(name) if is_reserved(&name.to_string_lossy()) => {
+                return Err(PathError::Reserved(decoded.into_owned()));
+            }
+            Component::Normal(_) | Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(PathError::Forbidden(decoded.into_owned()));
+            }
+        }
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs, process,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    /// A directory tree under the system temporary directory, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let path = env::temp_dir().join(format!(
+                "resolve-test-{}-{}",
+                process::id(),
+                COUNT.fetch_add(1, Ordering::Relaxed)
+            ));
+            fs::create_dir_all(&path).unwrap();
+            Self(path.canonicalize().unwrap())
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Creates `root` with some files, next to an `outside` directory holding
+    /// a file that must never be reachable.
+    fn tree() -> (TempDir, PathBuf) {
+        let temp = TempDir::new();
+        let root = temp.0.join("root");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir_all(temp.0.join("outside")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("sub/b.txt"), "b").unwrap();
+        fs::write(root.join(".upload~a.txt.0.tmp"), "partial").unwrap();
+        fs::write(temp.0.join("outside/secret.txt"), "secret").unwrap();
+        (temp, root)
+    }
+
+    #[test]
+    fn resolves_paths_under_the_root() {
+        let (_temp, root) = tree();
+        assert_eq!(resolve(&root, "a.txt").unwrap(), root.join("a.txt"));
+        assert_eq!(resolve(&root, "sub/b.txt").unwrap(), root.join("sub/b.txt"));
+        assert_eq!(
+            resolve(&root, "sub%2Fb.txt").unwrap(),
+            root.join("sub/b.txt")
+        );
+        assert_eq!(
+            resolve(&root, "./sub//b.txt").unwrap(),
+            root.join("sub/b.txt")
+        );
+        assert_eq!(resolve(&root, "").unwrap(), root);
+    }
+
+    #[test]
+    fn rejects_parent_directories() {
+        let (_temp, root) = tree();
+        for raw in [
+            "../outside/secret.txt",
+            "%2e%2e/outside/secret.txt",
+            "%2E%2E%2Foutside%2Fsecret.txt",
+            "sub/../../outside/secret.txt",
+            "sub/..%2f..%2foutside/secret.txt",
+        ] {
+            assert!(
+                matches!(resolve(&root, raw), Err(PathError::Forbidden(_))),
+                "{raw:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        let (temp, root) = tree();
+        let absolute = temp.0.join("outside/secret.txt");
+        let raw = absolute.to_str().unwrap().replace('/', "%2F");
+        assert!(matches!(resolve(&root, &raw), Err(PathError::Forbidden(_))));
+        assert!(matches!(
+            resolve(&root, "%2Fetc%2Fpasswd"),
+            Err(PathError::Forbidden(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        let (_temp, root) = tree();
+        for raw in ["a.txt%00", "a%00.txt", "%ff", "%c3%28"] {
+            assert!(
+                matches!(resolve(&root, raw), Err(PathError::Malformed(_))),
+                "{raw:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_reserved_names() {
+        let (_temp, root) = tree();
+        for raw in [
+            ".upload~a.txt.0.tmp",
+            ".upload%7Ea.txt.0.tmp",
+            "sub/.upload~x",
+        ] {
+            assert!(
+                matches!(resolve(&root, raw), Err(PathError::Reserved(_))),
+                "{raw:?}"
+            );
+        }
+        assert!(matches!(
+            resolve_for_write(&root, ".upload~new"),
+            Err(PathError::Reserved(_))
+        ));
+        assert!(is_reserved(".upload~x"));
+        assert!(!is_reserved("upload~x"));
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let (_temp, root) = tree();
+        match resolve(&root, "missing.txt") {
+            Err(PathError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn resolves_entries_and_new_files() {
+        let (_temp, root) = tree();
+        assert_eq!(
+            resolve_entry(&root, "new.txt").unwrap(),
+            root.join("new.txt")
+        );
+        assert_eq!(
+            resolve_for_write(&root, "sub/new.txt").unwrap(),
+            root.join("sub/new.txt")
+        );
+        assert!(matches!(
+            resolve_entry(&root, ""),
+            Err(PathError::Malformed(_))
+        ));
+        assert!(matches!(
+            resolve_for_write(&root, "missing/new.txt"),
+            Err(PathError::Io(_))
+        ));
+        assert!(matches!(
+            resolve_for_write(&root, "%2e%2e/outside/new.txt"),
+            Err(PathError::Forbidden(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    mod symlinks {
+        use std::os::unix::fs::symlink;
+
+        use super::*;
+
+        fn linked_tree() -> (TempDir, PathBuf) {
+            let (temp, root) = tree();
+            let outside = temp.0.join("outside");
+            symlink(root.join("a.txt"), root.join("inside.txt")).unwrap();
+            symlink(root.join("sub"), root.join("inside-dir")).unwrap();
+            symlink(outside.join("secret.txt"), root.join("escape.txt")).unwrap();
+            symlink(&outside, root.join("escape-dir")).unwrap();
+            symlink(root.join("missing.txt"), root.join("dangling.txt")).unwrap();
+            symlink(outside.join("missing.txt"), root.join("dangling-out.txt")).unwrap();
+            (temp, root)
+        }
+
+        #[test]
+        fn follows_symlinks_within_the_root() {
+            let (_temp, root) = linked_tree();
+            assert_eq!(resolve(&root, "inside.txt").unwrap(), root.join("a.txt"));
+            assert_eq!(
+                resolve(&root, "inside-dir/b.txt").unwrap(),
+                root.join("sub/b.txt")
+            );
+            assert_eq!(
+                resolve_for_write(&root, "inside.txt").unwrap(),
+                root.join("a.txt")
+            );
+        }
+
+        #[test]
+        fn rejects_symlinks_leading_outside() {
+            let (_temp, root) = linked_tree();
+            for raw in ["escape.txt", "escape-dir", "escape-dir/secret.txt"] {
+                assert!(
+                    matches!(resolve(&root, raw), Err(PathError::Forbidden(_))),
+                    "{raw:?}"
+                );
+            }
+            assert!(matches!(
+                resolve_for_write(&root, "escape.txt"),
+                Err(PathError::Forbidden(_))
+            ));
+            assert!(matches!(
+                resolve_for_write(&root, "escape-dir/new.txt"),
+                Err(PathError::Forbidden(_))
+            ));
+            assert!(matches!(
+                resolve_entry(&root, "escape-dir/secret.txt"),
+                Err(PathError::Forbidden(_))
+            ));
+        }
+
+        #[test]
+        fn names_symlinks_themselves_as_entries() {
+            let (_temp, root) = linked_tree();
+            assert_eq!(
+                resolve_entry(&root, "escape.txt").unwrap(),
+                root.join("escape.txt")
+            );
+        }
+
+        #[test]
+        fn does_not_write_through_dangling_symlinks() {
+            let (_temp, root) = linked_tree();
+            for raw in ["dangling.txt", "dangling-out.txt"] {
+                assert!(
+                    matches!(resolve(&root, raw), Err(PathError::Io(_))),
+                    "{raw:?}"
+                );
+                assert!(
+                    matches!(resolve_for_write(&root, raw), Err(PathError::Io(_))),
+                    "{raw:?}"
+                );
+            }
+        }
+    }
+}
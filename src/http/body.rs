@@ -0,0 +1,135 @@
+use std::{
+    fmt::{self, Formatter},
+    io::{self, Read, Write},
+};
+
+use crate::http::chunked::ChunkedWriter;
+
+/// A response body, either held in memory or produced while it is written.
+#[derive(Default)]
+pub enum Body {
+    #[default]
+    Empty,
+    Full(Vec<u8>),
+    /// A body read from `reader`. When `len` is known exactly that many bytes
+    /// are sent with `Content-Length`, otherwise the reader is drained using
+    /// the chunked transfer coding.
+    Reader {
+        reader: Box<dyn Read + Send>,
+        len: Option<u64>,
+    },
+}
+
+impl Body {
+    pub fn from_reader<R: Read + Send + 'static>(reader: R, len: Option<u64>) -> Self {
+        Body::Reader {
+            reader: Box::new(reader),
+            len,
+        }
+    }
+
+    /// Creates a body of unknown length from an iterator of chunks, so that
+    /// generated content can be streamed without materializing it first.
+    pub fn from_chunks<I>(chunks: I) -> Self
+    where
+        I: Iterator<Item = io::Result<Vec<u8>>> + Send + 'static,
+    {
+        Self::from_reader(ChunksReader::new(chunks), None)
+    }
+
+    /// Returns the exact length of the body if it is known up front.
+    pub fn content_length(&self) -> Option<u64> {
+        match self {
+            Body::Empty => Some(0),
+            Body::Full(buf) => Some(buf.len() as u64),
+            Body::Reader { len, .. } => *len,
+        }
+    }
+
+    /// Writes the body to `w`, using the chunked transfer coding if `chunked`.
+    pub fn write_to<W: Write>(self, w: &mut W, chunked: bool) -> io::Result<()> {
+        if chunked {
+            let mut w = ChunkedWriter::new(w);
+            self.copy_to(&mut w)?;
+            return w.finish();
+        }
+        self.copy_to(w)
+    }
+
+    fn copy_to<W: Write>(self, w: &mut W) -> io::Result<()> {
+        match self {
+            Body::Empty => Ok(()),
+            Body::Full(buf) => w.write_all(&buf),
+            Body::Reader {
+                reader,
+                len: Some(len),
+            } => {
+                let copied = io::copy(&mut reader.take(len), w)?;
+                if copied != len {
+                    // The length has already been promised in Content-Length.
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("body ended after {} of {} bytes", copied, len),
+                    ));
+                }
+                Ok(())
+            }
+            Body::Reader {
+                mut reader,
+                len: None,
+            } => io::copy(&mut reader, w).map(|_| ()),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(buf: Vec<u8>) -> Self {
+        Body::Full(buf)
+    }
+}
+
+impl From<Option<Vec<u8>>> for Body {
+    fn from(buf: Option<Vec<u8>>) -> Self {
+        buf.map_or(Body::Empty, Body::Full)
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Body::Empty => f.write_str("Empty"),
+            Body::Full(buf) => write!(f, "Full({} bytes)", buf.len()),
+            Body::Reader { len: Some(len), .. } => write!(f, "Reader({} bytes)", len),
+            Body::Reader { len: None, .. } => f.write_str("Reader(unknown length)"),
+        }
+    }
+}
+
+struct ChunksReader<I> {
+    chunks: I,
+    current: io::Cursor<Vec<u8>>,
+}
+
+impl<I> ChunksReader<I> {
+    fn new(chunks: I) -> Self {
+        Self {
+            chunks,
+            current: io::Cursor::new(Vec::new()),
+        }
+    }
+}
+
+impl<I: Iterator<Item = io::Result<Vec<u8>>>> Read for ChunksReader<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.current.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            match self.chunks.next() {
+                Some(chunk) => self.current = io::Cursor::new(chunk?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::io::{self, BufRead, Write};
+
+use crate::http::{
+    header::{is_token, HeaderMap},
+    limits::{self, LimitExceeded, Limits},
+    request::{self, ParseError},
+};
+
+// Chunk sizes are limited to 16 hex digits so they always fit in a u64.
+const MAX_CHUNK_SIZE_DIGITS: usize = 16;
+
+/// Decodes a body sent with `Transfer-Encoding: chunked` (RFC 9112 section 7.1).
+///
+/// Reads chunks until the terminating zero-size chunk, then the trailer
+/// section up to and including the final empty line. Chunk extensions are
+/// validated and discarded. Returns the decoded body and the trailer fields.
+pub fn decode<R: BufRead>(
+    reader: &mut R,
+    limits: &Limits,
+) -> Result<(Vec<u8>, HeaderMap), ParseError> {
+    let mut body = Vec::new();
+
+    loop {
+        let line = read_line(reader, limits)?;
+        let size = parse_chunk_size(&line)?;
+        if size == 0 {
+            break;
+        }
+        if body
+            .len()
+            .checked_add(size)
+            .map_or(true, |len| len as u64 > limits.body)
+        {
+            return Err(LimitExceeded::Body(limits.body).into());
+        }
+
+        limits::read_body(reader, size as u64, &mut body)?;
+
+        if !read_line(reader, limits)?.is_empty() {
+            return Err(ParseError::Chunked("chunk data not followed by CRLF"));
+        }
+    }
+
+    let trailers = request::read_fields(reader, limits)?;
+    Ok((body, trailers))
+}
+
+fn parse_chunk_size(line: &str) -> Result<usize, ParseError> {
+    let (size, extensions) = match line.split_once(';') {
+        Some((size, extensions)) => (size.trim_end_matches([' ', '\t']), Some(extensions)),
+        None => (line, None),
+    };
+
+    if size.is_empty()
+        || size.len() > MAX_CHUNK_SIZE_DIGITS
+        || !size.bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        return Err(ParseError::Chunked("malformed chunk size"));
+    }
+    if let Some(extensions) = extensions {
+        validate_extensions(extensions)?;
+    }
+
+    u64::from_str_radix(size, 16)
+        .ok()
+        .and_then(|size| usize::try_from(size).ok())
+        .ok_or(ParseError::Chunked("chunk size too large"))
+}
+
+// chunk-ext = *( BWS ";" BWS chunk-ext-name [ BWS "=" BWS chunk-ext-val ] )
+fn validate_extensions(extensions: &str) -> Result<(), ParseError> {
+    for extension in split_extensions(extensions)? {
+        let (name, value) = match extension.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (extension.trim(), None),
+        };
+        if !is_token(name) {
+            return Err(ParseError::Chunked("malformed chunk extension name"));
+        }
+        if let Some(value) = value {
+            if !is_token(value) && !is_quoted_string(value) {
+                return Err(ParseError::Chunked("malformed chunk extension value"));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Splits on ';' outside of quoted strings.
+fn split_extensions(extensions: &str) -> Result<Vec<&str>, ParseError> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut escaped = false;
+
+    for (i, c) in extensions.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                parts.push(&extensions[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if in_quotes {
+        return Err(ParseError::Chunked(
+            "unterminated quoted string in chunk extension",
+        ));
+    }
+    parts.push(&extensions[start..]);
+    Ok(parts)
+}
+
+// Chunk size lines are held to the same limit as header lines.
+fn read_line<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<String, ParseError> {
+    limits::read_line(
+        reader,
+        limits.header_line,
+        LimitExceeded::HeaderLine(limits.header_line),
+        ParseError::HeaderField,
+    )
+}
+
+fn is_quoted_string(s: &str) -> bool {
+    let Some(inner) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) else {
+        return false;
+    };
+    let mut escaped = false;
+    for c in inner.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return false,
+            _ => {}
+        }
+    }
+    !escaped
+}
+
+/// Writes everything passed to it as chunks of the chunked transfer coding.
+///
+/// [`ChunkedWriter::finish`] must be called to send the terminating chunk.
+pub struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Writes the last chunk and an empty trailer section.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A zero-size chunk would terminate the body early.
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.inner, "{:x}\r\n", buf.len())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_str(input: &str) -> Result<(Vec<u8>, HeaderMap), ParseError> {
+        decode(&mut input.as_bytes(), &Limits::default())
+    }
+
+    #[test]
+    fn decodes_chunks_and_trailers() {
+        let (body, trailers) = decode_str(
+            "4\r\nWiki\r\n5\r\npedia\r\nE\r\n in\r\n\r\nchunks.\r\n0\r\nExpires: never\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(body, b"Wikipedia in\r\n\r\nchunks.");
+        assert_eq!(trailers.get("expires"), Some("never"));
+    }
+
+    #[test]
+    fn accepts_extensions() {
+        let (body, _) =
+            decode_str("3;name=value;flag\r\nabc\r\n2 ; q=\"a;\\\"b\"\r\nde\r\n0\r\n\r\n").unwrap();
+        assert_eq!(body, b"abcde");
+    }
+
+    #[test]
+    fn rejects_malformed_extensions() {
+        for input in [
+            "3;=v\r\nabc\r\n0\r\n\r\n",
+            "3;n=a b\r\nabc\r\n0\r\n\r\n",
+            "3;n=\"open\r\nabc\r\n0\r\n\r\n",
+        ] {
+            assert!(
+                matches!(decode_str(input), Err(ParseError::Chunked(_))),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_chunk_sizes() {
+        for input in ["\r\n", "x\r\n", "-1\r\n", "10000000000000000\r\n"] {
+            assert!(
+                matches!(decode_str(input), Err(ParseError::Chunked(_))),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_chunk_data_without_crlf() {
+        assert!(matches!(
+            decode_str("3\r\nabcd\r\n0\r\n\r\n"),
+            Err(ParseError::Chunked(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_body() {
+        assert!(matches!(decode_str("5\r\nabc"), Err(ParseError::Truncated)));
+        assert!(matches!(
+            decode_str("3\r\nabc\r\n"),
+            Err(ParseError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn rejects_oversize_chunks() {
+        let limits = Limits {
+            body: 4,
+            ..Limits::default()
+        };
+        for input in [
+            "5\r\nabcde\r\n0\r\n\r\n",
+            "2\r\nab\r\n3\r\nabc\r\n0\r\n\r\n",
+        ] {
+            assert!(
+                matches!(
+                    decode(&mut input.as_bytes(), &limits),
+                    Err(ParseError::TooLarge(LimitExceeded::Body(4)))
+                ),
+                "{input:?}"
+            );
+        }
+        // Announcing a huge chunk fails before any of it is read.
+        assert!(matches!(
+            decode_str("FFFFFFFFFFFFFFFF\r\n"),
+            Err(ParseError::TooLarge(_))
+        ));
+    }
+}
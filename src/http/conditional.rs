@@ -0,0 +1,378 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::http::{date, header::HeaderMap};
+
+/// An entity tag (RFC 9110 section 8.8.3).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityTag {
+    pub weak: bool,
+    /// The opaque tag, without the surrounding quotes.
+    pub tag: String,
+}
+
+impl EntityTag {
+    pub fn strong(tag: impl Into<String>) -> Self {
+        Self {
+            weak: false,
+            tag: tag.into(),
+        }
+    }
+
+    pub fn weak(tag: impl Into<String>) -> Self {
+        Self {
+            weak: true,
+            tag: tag.into(),
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (weak, quoted) = match s.strip_prefix("W/") {
+            Some(quoted) => (true, quoted),
+            None => (false, s),
+        };
+        let tag = quoted.strip_prefix('"')?.strip_suffix('"')?;
+        // etagc = %x21 / %x23-7E / obs-text
+        if !tag.bytes().all(|b| b == 0x21 || (b >= 0x23 && b != 0x7f)) {
+            return None;
+        }
+        Some(Self {
+            weak,
+            tag: tag.to_string(),
+        })
+    }
+
+    /// Both tags are strong and identical.
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// The tags are identical, ignoring weakness.
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl Display for EntityTag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+/// The validators of the selected representation of a resource.
+#[derive(Debug, Default)]
+pub struct Validators {
+    pub etag: Option<EntityTag>,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    /// Adds `ETag` and `Last-Modified` headers for the known validators.
+    pub fn write_headers(&self, headers: &mut HeaderMap) {
+        if let Some(etag) = &self.etag {
+            headers.insert("ETag", etag.to_string());
+        }
+        if let Some(last_modified) = self.last_modified {
+            headers.insert("Last-Modified", date::format(last_modified));
+        }
+    }
+
+    /// Evaluates `If-Range` (RFC 9110 section 13.1.5): whether a `Range`
+    /// header still applies to this representation. Only strong validators can
+    /// match, since ranges need byte-for-byte equality.
+    pub fn if_range_matches(&self, headers: &HeaderMap) -> bool {
+        let Some(if_range) = headers.get("If-Range") else {
+            return true;
+        };
+
+        if let Some(tag) = EntityTag::parse(if_range) {
+            return self.etag.as_ref().is_some_and(|etag| etag.strong_eq(&tag));
+        }
+        match (date::parse(if_range), self.last_modified) {
+            (Some(date), Some(last_modified)) => date == truncate_to_secs(last_modified),
+            _ => false,
+        }
+    }
+}
+
+/// The result of evaluating the preconditions of a request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Precondition {
+    /// Process the request normally.
+    Proceed,
+    /// Answer 304 Not Modified.
+    NotModified,
+    /// Answer 412 Precondition Failed.
+    Failed,
+}
+
+/// Evaluates the preconditions of a request against the current state of the
+/// target resource, in the order given by RFC 9110 section 13.2.2.
+///
+/// `validators` is `None` if the resource does not exist.
+pub fn evaluate(
+    headers: &HeaderMap,
+    method: &str,
+    validators: Option<&Validators>,
+) -> Precondition {
+    let etag = validators.and_then(|v| v.etag.as_ref());
+    let last_modified = validators.and_then(|v| v.last_modified);
+
+    if let Some(if_match) = joined(headers, "If-Match") {
+        if !list_matches(&if_match, validators.is_some(), |tag| {
+            etag.is_some_and(|etag| etag.strong_eq(tag))
+        }) {
+            return Precondition::Failed;
+        }
+    } else if let Some(since) = headers.get("If-Unmodified-Since").and_then(date::parse) {
+        if last_modified.is_some_and(|modified| truncate_to_secs(modified) > since) {
+            return Precondition::Failed;
+        }
+    }
+
+    let is_get = method == "GET" || method == "HEAD";
+    if let Some(if_none_match) = joined(headers, "If-None-Match") {
+        if list_matches(&if_none_match, validators.is_some(), |tag| {
+            etag.is_some_and(|etag| etag.weak_eq(tag))
+        }) {
+            return if is_get {
+                Precondition::NotModified
+            } else {
+                Precondition::Failed
+            };
+        }
+    } else if is_get {
+        if let Some(since) = headers.get("If-Modified-Since").and_then(date::parse) {
+            if last_modified.is_some_and(|modified| truncate_to_secs(modified) <= since) {
+                return Precondition::NotModified;
+            }
+        }
+    }
+
+    Precondition::Proceed
+}
+
+fn joined(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .contains_key(name)
+        .then(|| headers.get_all(name).collect::<Vec<_>>().join(","))
+}
+
+/// Matches an `If-Match`/`If-None-Match` value: `*` matches any existing
+/// representation, otherwise any listed tag accepted by `matches` does.
+fn list_matches(list: &str, exists: bool, matches: impl Fn(&EntityTag) -> bool) -> bool {
+    if list.trim() == "*" {
+        return exists;
+    }
+    parse_list(list).iter().any(matches)
+}
+
+// Entity tags may contain commas, so the list is split on closing quotes.
+fn parse_list(list: &str) -> Vec<EntityTag> {
+    let mut tags = Vec::new();
+    let mut rest = list;
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ',']);
+        let start = if rest.starts_with("W/") { 3 } else { 1 };
+        let Some(end) = rest.get(start..).and_then(|s| s.find('"')) else {
+            break;
+        };
+        let (item, tail) = rest.split_at(start + end + 1);
+        tags.extend(EntityTag::parse(item));
+        rest = tail;
+    }
+    tags
+}
+
+/// Drops sub-second precision, which HTTP dates cannot express.
+pub fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => UNIX_EPOCH + Duration::from_secs(since_epoch.as_secs()),
+        Err(_) => time,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODIFIED: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+    const BEFORE: &str = "Sat, 05 Nov 1994 08:49:37 GMT";
+    const AFTER: &str = "Mon, 07 Nov 1994 08:49:37 GMT";
+
+    fn validators() -> Validators {
+        Validators {
+            etag: Some(EntityTag::strong("abc")),
+            // Sub-second precision is ignored when comparing.
+            last_modified: Some(date::parse(MODIFIED).unwrap() + Duration::from_millis(500)),
+        }
+    }
+
+    fn check(method: &str, fields: &[(&str, &str)]) -> Precondition {
+        let mut headers = HeaderMap::new();
+        for (name, value) in fields {
+            headers.append(*name, *value);
+        }
+        evaluate(&headers, method, Some(&validators()))
+    }
+
+    #[test]
+    fn parses_entity_tags() {
+        assert_eq!(EntityTag::parse("\"abc\""), Some(EntityTag::strong("abc")));
+        assert_eq!(EntityTag::parse("W/\"abc\""), Some(EntityTag::weak("abc")));
+        assert_eq!(EntityTag::parse("\"\""), Some(EntityTag::strong("")));
+        assert_eq!(EntityTag::parse("abc"), None);
+        assert_eq!(EntityTag::parse("\"a\"b\""), None);
+        assert_eq!(EntityTag::weak("x").to_string(), "W/\"x\"");
+    }
+
+    #[test]
+    fn compares_entity_tags() {
+        let (strong, weak) = (EntityTag::strong("a"), EntityTag::weak("a"));
+        assert!(strong.strong_eq(&strong));
+        assert!(!strong.strong_eq(&weak));
+        assert!(strong.weak_eq(&weak));
+        assert!(!strong.weak_eq(&EntityTag::strong("b")));
+    }
+
+    #[test]
+    fn proceeds_without_preconditions() {
+        assert_eq!(check("GET", &[]), Precondition::Proceed);
+    }
+
+    #[test]
+    fn if_match_requires_a_strong_match() {
+        assert_eq!(
+            check("PUT", &[("If-Match", "\"abc\"")]),
+            Precondition::Proceed
+        );
+        assert_eq!(
+            check("PUT", &[("If-Match", "\"x\", \"abc\"")]),
+            Precondition::Proceed
+        );
+        assert_eq!(
+            check("PUT", &[("If-Match", "W/\"abc\"")]),
+            Precondition::Failed
+        );
+        assert_eq!(check("PUT", &[("If-Match", "*")]), Precondition::Proceed);
+        let mut headers = HeaderMap::new();
+        headers.insert("If-Match", "*");
+        assert_eq!(evaluate(&headers, "PUT", None), Precondition::Failed);
+    }
+
+    #[test]
+    fn if_match_takes_precedence_over_if_unmodified_since() {
+        assert_eq!(
+            check(
+                "PUT",
+                &[("If-Match", "\"abc\""), ("If-Unmodified-Since", BEFORE)]
+            ),
+            Precondition::Proceed
+        );
+        assert_eq!(
+            check("PUT", &[("If-Unmodified-Since", BEFORE)]),
+            Precondition::Failed
+        );
+        assert_eq!(
+            check("PUT", &[("If-Unmodified-Since", MODIFIED)]),
+            Precondition::Proceed
+        );
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        assert_eq!(
+            check("GET", &[("If-None-Match", "W/\"abc\"")]),
+            Precondition::NotModified
+        );
+        assert_eq!(
+            check("HEAD", &[("If-None-Match", "\"x\", \"abc\"")]),
+            Precondition::NotModified
+        );
+        assert_eq!(
+            check("GET", &[("If-None-Match", "\"x\"")]),
+            Precondition::Proceed
+        );
+        assert_eq!(
+            check("PUT", &[("If-None-Match", "*")]),
+            Precondition::Failed
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert("If-None-Match", "*");
+        assert_eq!(evaluate(&headers, "PUT", None), Precondition::Proceed);
+    }
+
+    #[test]
+    fn if_none_match_takes_precedence_over_if_modified_since() {
+        assert_eq!(
+            check(
+                "GET",
+                &[("If-None-Match", "\"x\""), ("If-Modified-Since", AFTER)]
+            ),
+            Precondition::Proceed
+        );
+        assert_eq!(
+            check("GET", &[("If-Modified-Since", MODIFIED)]),
+            Precondition::NotModified
+        );
+        assert_eq!(
+            check("GET", &[("If-Modified-Since", BEFORE)]),
+            Precondition::Proceed
+        );
+    }
+
+    #[test]
+    fn if_modified_since_only_applies_to_get_and_head() {
+        assert_eq!(
+            check("POST", &[("If-Modified-Since", AFTER)]),
+            Precondition::Proceed
+        );
+    }
+
+    #[test]
+    fn failed_if_match_wins_over_if_none_match() {
+        assert_eq!(
+            check(
+                "GET",
+                &[("If-Match", "\"x\""), ("If-None-Match", "\"abc\"")]
+            ),
+            Precondition::Failed
+        );
+    }
+
+    #[test]
+    fn ignores_invalid_dates() {
+        assert_eq!(
+            check("GET", &[("If-Modified-Since", "yesterday")]),
+            Precondition::Proceed
+        );
+        assert_eq!(
+            check(
+                "PUT",
+                &[("If-Unmodified-Since", "Sun, 31 Feb 1994 00:00:00 GMT")]
+            ),
+            Precondition::Proceed
+        );
+    }
+
+    #[test]
+    fn evaluates_if_range() {
+        let validators = validators();
+        let if_range = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("If-Range", value);
+            validators.if_range_matches(&headers)
+        };
+        assert!(validators.if_range_matches(&HeaderMap::new()));
+        assert!(if_range("\"abc\""));
+        assert!(!if_range("W/\"abc\""));
+        assert!(if_range(MODIFIED));
+        assert!(!if_range(AFTER));
+    }
+}
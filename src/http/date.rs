@@ -0,0 +1,223 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Formats `time` as an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+///
+/// Sub-second precision is dropped, and times before 1970 are clamped to the
+/// epoch.
+pub fn format(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    let days = secs / 86400;
+    let secs_of_day = secs % 86400;
+    let (year, month, day) = civil_from_days(days as i64);
+
+    format!(
+        "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Parses an HTTP-date in any of the three formats recipients must accept
+/// (RFC 9110 section 5.6.7): IMF-fixdate, RFC 850 and asctime.
+///
+/// Dates that do not exist, or whose year is outside 1 to 9999, are rejected
+/// like malformed ones, as are dates before 1970, which cannot be represented.
+pub fn parse(s: &str) -> Option<SystemTime> {
+    let s = s.trim();
+    let fields = s.split_ascii_whitespace().collect::<Vec<_>>();
+
+    let (day, month, year, time) = match fields.as_slice() {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        [_, day, month, year, time, "GMT"] => (*day, *month, year.parse().ok()?, *time),
+        // Sunday, 06-Nov-94 08:49:37 GMT
+        [_, date, time, "GMT"] => {
+            let mut parts = date.split('-');
+            let (day, month, year) = (parts.next()?, parts.next()?, parts.next()?);
+            if parts.next().is_some() || year.len() != 2 {
+                return None;
+            }
+            let year = expand_year(year.parse().ok()?, current_year());
+            (day, month, year, *time)
+        }
+        // Sun Nov  6 08:49:37 1994
+        [_, month, day, time, year] => (*day, *month, year.parse().ok()?, *time),
+        _ => return None,
+    };
+
+    let day: u32 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let mut hms = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (hour, minute, second) = (hms.next()??, hms.next()??, hms.next()??);
+    if hms.next().is_some() || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    if !(1..=9999).contains(&year) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let secs = days
+        .checked_mul(86400)?
+        .checked_add(hour * 3600 + minute * 60 + second)?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+/// Expands the two-digit year of an RFC 850 date: it is in the current
+/// century, unless that is more than 50 years in the future, in which case it
+/// is in the previous one (RFC 9110 section 5.6.7).
+fn expand_year(two_digit: i64, current_year: i64) -> i64 {
+    let year = current_year - current_year.rem_euclid(100) + two_digit;
+    if year > current_year + 50 {
+        year - 100
+    } else {
+        year
+    }
+}
+
+fn current_year() -> i64 {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    civil_from_days((secs / 86400) as i64).0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 to a proleptic Gregorian date, after Howard Hinnant's
+// `civil_from_days` and `days_from_civil` algorithms.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sun, 06 Nov 1994 08:49:37 GMT
+    const EXAMPLE: u64 = 784111777;
+
+    fn secs(s: &str) -> Option<u64> {
+        parse(s).map(|t| t.duration_since(UNIX_EPOCH).unwrap().as_secs())
+    }
+
+    #[test]
+    fn formats_imf_fixdate() {
+        let time = UNIX_EPOCH + Duration::from_secs(EXAMPLE);
+        assert_eq!(format(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(format(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn parses_all_three_formats() {
+        assert_eq!(secs("Sun, 06 Nov 1994 08:49:37 GMT"), Some(EXAMPLE));
+        assert_eq!(secs("Sunday, 06-Nov-94 08:49:37 GMT"), Some(EXAMPLE));
+        assert_eq!(secs("Sun Nov  6 08:49:37 1994"), Some(EXAMPLE));
+    }
+
+    #[test]
+    fn round_trips() {
+        for secs in [0, EXAMPLE, 951782400, 4102444799] {
+            let time = UNIX_EPOCH + Duration::from_secs(secs);
+            assert_eq!(parse(&format(time)), Some(time));
+        }
+    }
+
+    #[test]
+    fn expands_two_digit_years_within_50_years() {
+        assert_eq!(expand_year(94, 2026), 1994);
+        assert_eq!(expand_year(75, 2026), 2075);
+        assert_eq!(expand_year(76, 2026), 2076);
+        assert_eq!(expand_year(77, 2026), 1977);
+        assert_eq!(expand_year(0, 2026), 2000);
+        assert_eq!(expand_year(40, 2090), 2040);
+        assert_eq!(expand_year(95, 2090), 2095);
+        assert_eq!(expand_year(99, 2000), 1999);
+    }
+
+    #[test]
+    fn rejects_impossible_dates() {
+        for s in [
+            "Sun, 31 Feb 2020 00:00:00 GMT",
+            "Sun, 29 Feb 2019 00:00:00 GMT",
+            "Sun, 29 Feb 1900 00:00:00 GMT",
+            "Sun, 31 Apr 2020 00:00:00 GMT",
+            "Sun, 00 Jan 2020 00:00:00 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:60:00 GMT",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+        ] {
+            assert_eq!(parse(s), None, "{s:?}");
+        }
+        assert!(parse("Sat, 29 Feb 2020 00:00:00 GMT").is_some());
+        assert!(parse("Tue, 29 Feb 2000 00:00:00 GMT").is_some());
+    }
+
+    #[test]
+    fn rejects_out_of_range_years() {
+        for s in [
+            "Sun, 06 Nov 999999999999 08:49:37 GMT",
+            "Sun, 06 Nov -999999999999 08:49:37 GMT",
+            "Sun, 06 Nov 10000 08:49:37 GMT",
+            "Sun, 06 Nov 0 08:49:37 GMT",
+            "Sun, 06 Nov 1969 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 99999999999999999",
+        ] {
+            assert_eq!(parse(s), None, "{s:?}");
+        }
+        assert!(parse("Fri, 31 Dec 9999 23:59:59 GMT").is_some());
+    }
+
+    #[test]
+    fn rejects_malformed_dates() {
+        for s in [
+            "",
+            "Sun, 06 Nov 1994 08:49:37",
+            "Sun, 06 Nov 1994 08:49 GMT",
+            "Sun, 06 Nov 1994 08:49:37:00 GMT",
+            "Sunday, 06-Nov-1994 08:49:37 GMT",
+            "1994-11-06T08:49:37Z",
+        ] {
+            assert_eq!(parse(s), None, "{s:?}");
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use md5::Md5;
+use sha2::{Digest, Sha256, Sha512};
+use thiserror::Error;
+
+use crate::http::header::HeaderMap;
+
+/// A request body that does not match the digest its sender declared.
+#[derive(Debug, Error)]
+pub enum DigestError {
+    #[error("malformed {0} header")]
+    Malformed(&'static str),
+    #[error("body does not match its {0} digest")]
+    Mismatch(&'static str),
+}
+
+/// Checks `body` against the `Content-Digest` (RFC 9530) and `Content-MD5`
+/// (RFC 1864) headers, when present.
+///
+/// `Content-Digest` entries using algorithms other than `sha-256` and
+/// `sha-512` are ignored.
+pub fn verify(headers: &HeaderMap, body: &[u8]) -> Result<(), DigestError> {
+    let content_digest = headers.get_all("Content-Digest").collect::<Vec<_>>();
+    for entry in content_digest.iter().flat_map(|value| value.split(',')) {
+        let (algorithm, value) = entry
+            .split_once('=')
+            .ok_or(DigestError::Malformed("Content-Digest"))?;
+        let algorithm = algorithm.trim().to_ascii_lowercase();
+        let (name, actual) = match algorithm.as_str() {
+            "sha-256" => ("sha-256", Sha256::digest(body).to_vec()),
+            "sha-512" => ("sha-512", Sha512::digest(body).to_vec()),
+            _ => continue,
+        };
+        // Digests are structured field byte sequences: base64 between colons.
+        let expected = value
+            .trim()
+            .strip_prefix(':')
+            .and_then(|v| v.strip_suffix(':'))
+            .and_then(|v| STANDARD.decode(v).ok())
+            .ok_or(DigestError::Malformed("Content-Digest"))?;
+        if expected != actual {
+            return Err(DigestError::Mismatch(name));
+        }
+    }
+
+    if let Some(value) = headers.get("Content-MD5") {
+        let expected = STANDARD
+            .decode(value.trim())
+            .map_err(|_| DigestError::Malformed("Content-MD5"))?;
+        if expected != Md5::digest(body).as_slice() {
+            return Err(DigestError::Mismatch("Content-MD5"));
+        }
+    }
+    Ok(())
+}
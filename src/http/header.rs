@@ -0,0 +1,264 @@
+use std::fmt::{self, Formatter};
+
+use thiserror::Error;
+
+/// An ordered collection of header fields.
+///
+/// Names are compared case-insensitively but keep the spelling they were
+/// inserted with. Fields are serialized in insertion order, and a name may
+/// appear more than once (e.g. `Set-Cookie`).
+#[derive(Clone, Default)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+#[derive(Debug, Error)]
+pub enum InvalidHeader {
+    #[error("invalid header name: {0:?}")]
+    Name(String),
+    #[error("invalid value for header {0}")]
+    Value(String),
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the first value of the field `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns every value of the field `name`, in the order they were added.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets the field `name` to `value`, replacing any existing values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name or value is not valid; see [`HeaderMap::try_insert`].
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        if let Err(e) = self.try_insert(name, value) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Adds a value for the field `name`, keeping any existing values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name or value is not valid; see [`HeaderMap::try_append`].
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        if let Err(e) = self.try_append(name, value) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like [`HeaderMap::insert`], but fails if `name` is not an RFC 9110
+    /// token or `value` is not a valid field value.
+    pub fn try_insert(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), InvalidHeader> {
+        let (name, value) = validate(name.into(), value.into())?;
+        match self
+            .entries
+            .iter()
+            .position(|(key, _)| key.eq_ignore_ascii_case(&name))
+        {
+            Some(i) => {
+                // Keep the position of the first occurrence and drop the rest.
+                let mut index = 0;
+                self.entries.retain(|(key, _)| {
+                    index += 1;
+                    index - 1 <= i || !key.eq_ignore_ascii_case(&name)
+                });
+                self.entries[i] = (name, value);
+            }
+            None => self.entries.push((name, value)),
+        }
+        Ok(())
+    }
+
+    /// Like [`HeaderMap::append`], but fails if `name` is not an RFC 9110
+    /// token or `value` is not a valid field value.
+    pub fn try_append(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), InvalidHeader> {
+        let (name, value) = validate(name.into(), value.into())?;
+        self.entries.push((name, value));
+        Ok(())
+    }
+
+    /// Removes every value of the field `name`, returning the first one.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let mut removed = None;
+        self.entries.retain_mut(|(key, value)| {
+            if !key.eq_ignore_ascii_case(name) {
+                return true;
+            }
+            if removed.is_none() {
+                removed = Some(std::mem::take(value));
+            }
+            false
+        });
+        removed
+    }
+
+    /// Iterates over all fields in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl fmt::Debug for HeaderMap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+fn validate(name: String, value: String) -> Result<(String, String), InvalidHeader> {
+    if !is_token(&name) {
+        return Err(InvalidHeader::Name(name));
+    }
+    // Surrounding whitespace is not part of the value (RFC 9110 section 5.5).
+    let value = value.trim_matches([' ', '\t']).to_string();
+    if !value.bytes().all(is_field_vchar) {
+        return Err(InvalidHeader::Value(name));
+    }
+    Ok((name, value))
+}
+
+// field-content characters: VCHAR, obs-text, SP and HTAB.
+fn is_field_vchar(b: u8) -> bool {
+    b == b' ' || b == b'\t' || (b >= 0x21 && b != 0x7f)
+}
+
+/// Returns whether `s` matches the RFC 9110 `token` grammar.
+pub fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(is_tchar)
+}
+
+fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(map: &HeaderMap) -> Vec<(&str, &str)> {
+        map.iter().collect()
+    }
+
+    #[test]
+    fn looks_up_names_case_insensitively() {
+        let mut map = HeaderMap::new();
+        map.append("Accept", "a");
+        map.append("ACCEPT", "b");
+        map.append("Host", "h");
+        assert_eq!(map.get("accept"), Some("a"));
+        assert_eq!(map.get_all("aCcEpT").collect::<Vec<_>>(), ["a", "b"]);
+        assert!(map.contains_key("HOST"));
+        assert_eq!(map.get("missing"), None);
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    fn keeps_the_spelling_and_order_of_names() {
+        let mut map = HeaderMap::new();
+        map.append("x-First", "1");
+        map.append("X-Second", "2");
+        assert_eq!(fields(&map), [("x-First", "1"), ("X-Second", "2")]);
+    }
+
+    #[test]
+    fn insert_replaces_in_the_first_position() {
+        let mut map = HeaderMap::new();
+        map.append("A", "1");
+        map.append("B", "2");
+        map.append("a", "3");
+        map.append("C", "4");
+        map.try_insert("A", "5").unwrap();
+        assert_eq!(fields(&map), [("A", "5"), ("B", "2"), ("C", "4")]);
+        map.try_insert("D", "6").unwrap();
+        assert_eq!(map.iter().last(), Some(("D", "6")));
+    }
+
+    #[test]
+    fn remove_returns_the_first_value() {
+        let mut map = HeaderMap::new();
+        map.append("A", "1");
+        map.append("B", "2");
+        map.append("a", "3");
+        assert_eq!(map.remove("a"), Some("1".to_string()));
+        assert_eq!(fields(&map), [("B", "2")]);
+        assert_eq!(map.remove("a"), None);
+    }
+
+    #[test]
+    fn trims_values() {
+        let mut map = HeaderMap::new();
+        map.append("A", " \tvalue with spaces\t ");
+        assert_eq!(map.get("A"), Some("value with spaces"));
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        let mut map = HeaderMap::new();
+        for name in ["", "Bad Name", "Bad:Name", "Bad\r\nName", "Ümlaut"] {
+            assert!(
+                matches!(map.try_append(name, "v"), Err(InvalidHeader::Name(_))),
+                "{name:?}"
+            );
+        }
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let mut map = HeaderMap::new();
+        for value in ["a\r\nInjected: 1", "a\nb", "a\rb", "a\0b", "a\x7fb"] {
+            assert!(
+                matches!(map.try_insert("A", value), Err(InvalidHeader::Value(_))),
+                "{value:?}"
+            );
+        }
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn recognizes_tokens() {
+        assert!(is_token("Content-Type"));
+        assert!(is_token("!#$%&'*+-.^_`|~09azAZ"));
+        assert!(!is_token(""));
+        assert!(!is_token("a b"));
+        assert!(!is_token("(a)"));
+    }
+}
@@ -0,0 +1,114 @@
+use std::io::{BufRead, Read};
+
+use thiserror::Error;
+
+use crate::http::{request::ParseError, status::StatusCode};
+
+/// Bounds on the size of a request, so that a client cannot make the server
+/// buffer arbitrary amounts of data.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Longest request line, in bytes.
+    pub request_line: usize,
+    /// Longest header or trailer field line, in bytes.
+    pub header_line: usize,
+    /// Most header fields, and separately most trailer fields.
+    pub header_count: usize,
+    /// Largest header section, and separately largest trailer section, in
+    /// bytes.
+    pub header_size: usize,
+    /// Largest body, in bytes, after removing any chunked framing.
+    pub body: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            request_line: 8 * 1024,
+            header_line: 8 * 1024,
+            header_count: 100,
+            header_size: 64 * 1024,
+            body: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// A request that exceeds one of its [`Limits`].
+#[derive(Debug, Error)]
+pub enum LimitExceeded {
+    #[error("request line longer than {0} bytes")]
+    RequestLine(usize),
+    #[error("header field longer than {0} bytes")]
+    HeaderLine(usize),
+    #[error("more than {0} header fields")]
+    HeaderCount(usize),
+    #[error("header section larger than {0} bytes")]
+    HeaderSize(usize),
+    #[error("body larger than {0} bytes")]
+    Body(u64),
+}
+
+impl LimitExceeded {
+    /// The status of the response rejecting the request.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            LimitExceeded::RequestLine(_) => StatusCode::URI_TOO_LONG,
+            LimitExceeded::HeaderLine(_)
+            | LimitExceeded::HeaderCount(_)
+            | LimitExceeded::HeaderSize(_) => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            LimitExceeded::Body(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+}
+
+/// Most memory reserved for a body before any of it arrives. Past this, the
+/// buffer grows with the data actually received, so that announcing a large
+/// body costs the client as much as it costs the server.
+const MAX_RESERVATION: u64 = 64 * 1024;
+
+/// Appends exactly `len` bytes from `reader` to `buf`, failing as truncated
+/// if the stream ends first.
+pub(crate) fn read_body<R: BufRead>(
+    reader: &mut R,
+    len: u64,
+    buf: &mut Vec<u8>,
+) -> Result<(), ParseError> {
+    buf.reserve(len.min(MAX_RESERVATION) as usize);
+    let read = (&mut *reader).take(len).read_to_end(buf)?;
+    if (read as u64) < len {
+        return Err(ParseError::Truncated);
+    }
+    Ok(())
+}
+
+/// Reads a line of at most `max` bytes, not counting its line terminator,
+/// which is removed. A bare LF is accepted as a terminator too.
+///
+/// Fails with `exceeded` if the line is longer, with `malformed` applied to a
+/// lossy copy of the line if it is not UTF-8, and as truncated if the stream
+/// ends before the line does.
+pub(crate) fn read_line<R: BufRead>(
+    reader: &mut R,
+    max: usize,
+    exceeded: LimitExceeded,
+    malformed: fn(String) -> ParseError,
+) -> Result<String, ParseError> {
+    let mut line = Vec::new();
+    // Room for the limit plus CRLF.
+    (&mut *reader)
+        .take(max as u64 + 2)
+        .read_until(b'\n', &mut line)?;
+
+    match line.strip_suffix(b"\n") {
+        Some(content) => {
+            let content = content.strip_suffix(b"\r").unwrap_or(content);
+            if content.len() > max {
+                return Err(exceeded.into());
+            }
+            String::from_utf8(content.to_vec())
+                .map_err(|e| malformed(String::from_utf8_lossy(e.as_bytes()).into_owned()))
+        }
+        None if line.len() as u64 == max as u64 + 2 => Err(exceeded.into()),
+        None => Err(ParseError::Truncated),
+    }
+}
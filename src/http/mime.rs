@@ -0,0 +1,172 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+/// How many leading bytes [`sniff`] looks at.
+pub const SNIFF_LEN: usize = 512;
+
+const DEFAULT_TYPES: &[(&str, &str)] = &[
+    // Text
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("csv", "text/csv"),
+    ("txt", "text/plain"),
+    ("text", "text/plain"),
+    ("log", "text/plain"),
+    ("md", "text/markdown"),
+    ("xml", "text/xml"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("webmanifest", "application/manifest+json"),
+    ("wasm", "application/wasm"),
+    // Images
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("bmp", "image/bmp"),
+    // Audio and video
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("flac", "audio/flac"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("mov", "video/quicktime"),
+    // Fonts
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    // Documents and archives
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("zst", "application/zstd"),
+    ("7z", "application/x-7z-compressed"),
+];
+
+/// Maps file extensions to media types.
+#[derive(Debug, Clone)]
+pub struct MimeTypes {
+    by_extension: HashMap<String, String>,
+}
+
+impl MimeTypes {
+    /// Maps `extension` (without the dot, case-insensitive) to `mime_type`,
+    /// replacing any existing mapping.
+    pub fn insert(&mut self, extension: &str, mime_type: &str) {
+        self.by_extension.insert(
+            extension.trim_start_matches('.').to_ascii_lowercase(),
+            mime_type.to_string(),
+        );
+    }
+
+    /// Adds the mappings of a `mime.types` file: lines of a media type
+    /// followed by its extensions, with `#` starting a comment.
+    pub fn load(&mut self, path: &Path) -> io::Result<()> {
+        for line in fs::read_to_string(path)?.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            if let Some(mime_type) = fields.next() {
+                for extension in fields {
+                    self.insert(extension, mime_type);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the media type for `path` from its extension.
+    pub fn lookup(&self, path: &Path) -> Option<&str> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        self.by_extension.get(&extension).map(String::as_str)
+    }
+}
+
+impl Default for MimeTypes {
+    fn default() -> Self {
+        let mut types = Self {
+            by_extension: HashMap::new(),
+        };
+        for (extension, mime_type) in DEFAULT_TYPES {
+            types.insert(extension, mime_type);
+        }
+        types
+    }
+}
+
+/// Adds `charset=utf-8` to textual media types that do not name a charset.
+pub fn with_charset(mime_type: &str) -> String {
+    let essence = mime_type.split(';').next().unwrap_or_default().trim();
+    let textual = essence.starts_with("text/")
+        || essence == "application/json"
+        || essence == "application/manifest+json"
+        || essence == "image/svg+xml";
+    if textual && !mime_type.to_ascii_lowercase().contains("charset=") {
+        format!("{}; charset=utf-8", mime_type)
+    } else {
+        mime_type.to_string()
+    }
+}
+
+/// Guesses a media type from the first bytes of a file's content.
+pub fn sniff(data: &[u8]) -> &'static str {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\x28\xb5\x2f\xfd", "application/zstd"),
+        (b"\0asm", "application/wasm"),
+        (b"wOFF", "font/woff"),
+        (b"wOF2", "font/woff2"),
+        (b"OggS", "audio/ogg"),
+        (b"ID3", "audio/mpeg"),
+        (b"fLaC", "audio/flac"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+    ];
+
+    if let Some((_, mime_type)) = SIGNATURES.iter().find(|(sig, _)| data.starts_with(sig)) {
+        return mime_type;
+    }
+    if data.len() >= 12 && data.starts_with(b"RIFF") {
+        match &data[8..12] {
+            b"WEBP" => return "image/webp",
+            b"WAVE" => return "audio/wav",
+            _ => {}
+        }
+    }
+    if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        return "video/mp4";
+    }
+
+    // A truncated multi-byte character at the end still counts as text.
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&data[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    };
+    match text {
+        Some(text) if !text.contains('\0') => {
+            let start = text.trim_start().to_ascii_lowercase();
+            if start.starts_with("<!doctype html") || start.starts_with("<html") {
+                "text/html"
+            } else if start.starts_with("<?xml") {
+                "text/xml"
+            } else {
+                "text/plain"
+            }
+        }
+        _ => "application/octet-stream",
+    }
+}
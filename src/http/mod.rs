@@ -1,3 +1,12 @@
+pub mod body;
+pub mod chunked;
+pub mod conditional;
+pub mod date;
+pub mod digest;
+pub mod header;
+pub mod limits;
+pub mod mime;
+pub mod range;
 pub mod request;
 pub mod response;
 pub mod status;
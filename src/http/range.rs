@@ -0,0 +1,193 @@
+use std::ops::Range;
+
+/// Requests with more ranges than this are served in full, so that a client
+/// cannot make the server assemble a huge number of tiny parts.
+const MAX_RANGES: usize = 32;
+
+/// The outcome of evaluating a `Range` header against a representation.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRanges {
+    /// The header is absent, malformed or not worth honoring: send everything.
+    Full,
+    /// The satisfiable ranges, in the order they were requested.
+    Partial(Vec<Range<u64>>),
+    /// None of the ranges overlap the representation.
+    Unsatisfiable,
+}
+
+/// Evaluates a `Range` header value (RFC 9110 section 14.2) for a
+/// representation of `len` bytes.
+pub fn parse(header: &str, len: u64) -> ByteRanges {
+    let Some(specs) = header.trim().strip_prefix("bytes=") else {
+        // Unknown range units are ignored.
+        return ByteRanges::Full;
+    };
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((first, last)) = spec.split_once('-') else {
+            return ByteRanges::Full;
+        };
+        let (first, last) = (first.trim(), last.trim());
+
+        let range = if first.is_empty() {
+            // suffix-range: the last `last` bytes.
+            let Some(suffix) = parse_pos(last) else {
+                return ByteRanges::Full;
+            };
+            if suffix == 0 {
+                continue;
+            }
+            len.saturating_sub(suffix)..len
+        } else {
+            let Some(first) = parse_pos(first) else {
+                return ByteRanges::Full;
+            };
+            let end = match last {
+                "" => len,
+                last => match parse_pos(last) {
+                    Some(last) if last >= first => last.saturating_add(1).min(len),
+                    _ => return ByteRanges::Full,
+                },
+            };
+            first..end
+        };
+
+        if range.start < range.end {
+            ranges.push(range);
+        }
+    }
+
+    if specs.trim().is_empty() {
+        ByteRanges::Full
+    } else if ranges.is_empty() {
+        ByteRanges::Unsatisfiable
+    } else if ranges.len() > MAX_RANGES {
+        ByteRanges::Full
+    } else {
+        ByteRanges::Partial(ranges)
+    }
+}
+
+fn parse_pos(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Formats the `Content-Range` value for `range` of a `len`-byte representation.
+pub fn content_range(range: &Range<u64>, len: u64) -> String {
+    format!("bytes {}-{}/{}", range.start, range.end - 1, len)
+}
+
+/// Parses a `Content-Range` value of the form `bytes first-last/complete`,
+/// where `complete` may be `*` if the complete length is unknown.
+pub fn parse_content_range(value: &str) -> Option<Range<u64>> {
+    let (range, complete) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let (first, last) = range.trim().split_once('-')?;
+    let (first, last) = (parse_pos(first)?, parse_pos(last)?);
+    if first > last {
+        return None;
+    }
+    match complete.trim() {
+        "*" => {}
+        complete if parse_pos(complete)? > last => {}
+        _ => return None,
+    }
+    Some(first..last.checked_add(1)?)
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_closed_and_open_ranges() {
+        assert_eq!(
+            parse("bytes=0-499", 1000),
+            ByteRanges::Partial(vec![0..500])
+        );
+        assert_eq!(
+            parse("bytes=500-", 1000),
+            ByteRanges::Partial(vec![500..1000])
+        );
+        assert_eq!(
+            parse("bytes=0-0, 10-19", 1000),
+            ByteRanges::Partial(vec![0..1, 10..20])
+        );
+    }
+
+    #[test]
+    fn clamps_ranges_to_the_length() {
+        assert_eq!(
+            parse("bytes=900-2000", 1000),
+            ByteRanges::Partial(vec![900..1000])
+        );
+    }
+
+    #[test]
+    fn parses_suffix_ranges() {
+        assert_eq!(
+            parse("bytes=-100", 1000),
+            ByteRanges::Partial(vec![900..1000])
+        );
+        assert_eq!(
+            parse("bytes=-5000", 1000),
+            ByteRanges::Partial(vec![0..1000])
+        );
+        assert_eq!(parse("bytes=-0", 1000), ByteRanges::Unsatisfiable);
+    }
+
+    #[test]
+    fn reports_unsatisfiable_ranges() {
+        assert_eq!(parse("bytes=1000-", 1000), ByteRanges::Unsatisfiable);
+        assert_eq!(parse("bytes=2000-3000", 1000), ByteRanges::Unsatisfiable);
+        assert_eq!(parse("bytes=0-10", 0), ByteRanges::Unsatisfiable);
+    }
+
+    #[test]
+    fn ignores_malformed_headers() {
+        for header in [
+            "items=0-10",
+            "bytes=",
+            "bytes=10-5",
+            "bytes=a-b",
+            "bytes=5",
+            "bytes=+1-2",
+            "bytes=0-10, x",
+        ] {
+            assert_eq!(parse(header, 1000), ByteRanges::Full, "{header:?}");
+        }
+    }
+
+    #[test]
+    fn ignores_too_many_ranges() {
+        let many = |n: u64| {
+            let specs = (0..n)
+                .map(|i| format!("{}-{}", i * 2, i * 2))
+                .collect::<Vec<_>>();
+            format!("bytes={}", specs.join(","))
+        };
+        assert!(matches!(
+            parse(&many(MAX_RANGES as u64), 1000),
+            ByteRanges::Partial(ranges) if ranges.len() == MAX_RANGES
+        ));
+        assert_eq!(parse(&many(MAX_RANGES as u64 + 1), 1000), ByteRanges::Full);
+    }
+
+    #[test]
+    fn formats_content_range() {
+        assert_eq!(content_range(&(0..500), 1000), "bytes 0-499/1000");
+    }
+
+    #[test]
+    fn parses_content_range() {
+        assert_eq!(parse_content_range("bytes 0-499/1000"), Some(0..500));
+        assert_eq!(parse_content_range("bytes 10-19/*"), Some(10..20));
+        assert_eq!(parse_content_range("bytes 10-19/19"), None);
+        assert_eq!(parse_content_range("bytes 19-10/*"), None);
+        assert_eq!(parse_content_range("bytes */1000"), None);
+    }
+}
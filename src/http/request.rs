@@ -1,85 +1,558 @@
 use std::{
-    collections::HashMap,
-    io::{BufRead, BufReader, Read, Result},
-    net::TcpStream,
+    io::{self, BufRead, ErrorKind},
+    str::Utf8Error,
 };
 
-use crate::http::version::Version;
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+use crate::{
+    http::{
+        chunked,
+        header::{is_token, HeaderMap, InvalidHeader},
+        limits::{self, LimitExceeded, Limits},
+        status::StatusCode,
+        version::Version,
+    },
+    state::State,
+};
+
+/// Why a request could not be read.
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("malformed request line {0:?}")]
+    RequestLine(String),
+    #[error("unsupported HTTP version {0:?}")]
+    Version(String),
+    #[error("malformed header field {0:?}")]
+    HeaderField(String),
+    #[error(transparent)]
+    InvalidHeader(#[from] InvalidHeader),
+    #[error("invalid Content-Length {0:?}")]
+    ContentLength(String),
+    #[error("both Content-Length and Transfer-Encoding are present")]
+    ConflictingLength,
+    #[error("unsupported transfer coding {0:?}")]
+    TransferCoding(String),
+    #[error("malformed chunked body: {0}")]
+    Chunked(&'static str),
+    #[error(transparent)]
+    TooLarge(#[from] LimitExceeded),
+    #[error("connection closed before the request was complete")]
+    Truncated,
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            ErrorKind::UnexpectedEof => ParseError::Truncated,
+            _ => ParseError::Io(e),
+        }
+    }
+}
+
+impl ParseError {
+    /// The status of the response rejecting the request. Of I/O errors, only
+    /// timeouts are worth answering, with 408 Request Timeout.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ParseError::Version(_) => StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+            ParseError::TransferCoding(_) => StatusCode::NOT_IMPLEMENTED,
+            ParseError::TooLarge(limit) => limit.status(),
+            ParseError::Io(_) => StatusCode::REQUEST_TIMEOUT,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Parts {
     pub method: String,
     pub path: String,
     pub version: Version,
-    pub headers: HashMap<String, String>,
+    pub headers: HeaderMap,
 }
 
 impl Parts {
-    pub fn new(request_line: String) -> Self {
-        let mut parts = request_line.splitn(3, ' ');
-        let method = parts.next().unwrap_or("").to_string();
-        let path = parts.next().unwrap_or("").to_string();
-        let version = parts.next().unwrap_or("").to_string();
+    /// Parses a request line, `method SP request-target SP HTTP-version`
+    /// (RFC 9112 section 3). Only HTTP/1.x is supported.
+    pub fn parse(request_line: &str) -> Result<Self, ParseError> {
+        let malformed = || ParseError::RequestLine(request_line.to_string());
+        let mut parts = request_line.split(' ');
+        let (Some(method), Some(path), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(malformed());
+        };
+        if !is_token(method) || path.is_empty() || path.bytes().any(|b| b.is_ascii_control()) {
+            return Err(malformed());
+        }
 
-        Self {
-            method,
-            path,
-            version: Version::from_str(&version),
-            headers: HashMap::new(),
+        // HTTP-version = "HTTP/" DIGIT "." DIGIT
+        let digits = version
+            .strip_prefix("HTTP/")
+            .and_then(|v| v.split_once('.'))
+            .filter(|(major, minor)| {
+                [major, minor]
+                    .iter()
+                    .all(|d| d.len() == 1 && d.bytes().all(|b| b.is_ascii_digit()))
+            })
+            .ok_or_else(malformed)?;
+        let version = match digits {
+            ("1", "0") => Version::HTTP_10,
+            // Later 1.x versions are compatible with 1.1 (RFC 9110 section 2.5).
+            ("1", _) => Version::HTTP_11,
+            _ => return Err(ParseError::Version(version.to_string())),
+        };
+
+        Ok(Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            version,
+            headers: HeaderMap::new(),
+        })
+    }
+
+    /// Whether the client wants the connection kept open after this request
+    /// (RFC 9112 section 9.3). HTTP/1.1 connections persist unless the client
+    /// sends `Connection: close`; HTTP/1.0 ones only with
+    /// `Connection: keep-alive`.
+    pub fn keep_alive(&self) -> bool {
+        let has_option = |option: &str| {
+            self.headers
+                .get_all("Connection")
+                .flat_map(|v| v.split(','))
+                .any(|o| o.trim().eq_ignore_ascii_case(option))
+        };
+        if has_option("close") {
+            return false;
         }
+        self.version >= Version::HTTP_11 || has_option("keep-alive")
     }
 }
+
 #[derive(Debug)]
 pub struct Request {
     pub head: Parts,
-    pub body: Option<String>,
+    pub body: Bytes,
+    pub trailers: HeaderMap,
+    /// Application state, attached by the router.
+    pub state: State,
 }
 
 impl Request {
-    pub fn new(head: Parts, body: Option<String>) -> Self {
-        Self { head, body }
+    pub fn new(head: Parts, body: Bytes) -> Self {
+        Self {
+            head,
+            body,
+            trailers: HeaderMap::new(),
+            state: State::new(),
+        }
+    }
+
+    /// Returns the body as text, failing if it is not valid UTF-8.
+    pub fn text(&self) -> std::result::Result<&str, Utf8Error> {
+        std::str::from_utf8(&self.body)
+    }
+
+    /// Deserializes the body as JSON.
+    pub fn json<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        serde_json::from_slice(&self.body)
     }
 }
 
 impl Request {
-    pub fn parse_request(stream: &TcpStream) -> Result<Request> {
-        let mut buf_reader = BufReader::new(stream);
-
-        let mut first_line = String::new();
-        buf_reader.read_line(&mut first_line)?;
-        let request_line = first_line.trim().to_string();
-
-        let mut parts = Parts::new(request_line);
-        loop {
-            let mut line = String::new();
-            buf_reader.read_line(&mut line)?;
-            let line = line.trim_end();
-            if line.is_empty() {
-                break;
+    /// Reads a complete request: its head, then its body.
+    pub fn parse_request<R: BufRead>(
+        buf_reader: &mut R,
+        limits: &Limits,
+    ) -> Result<Request, ParseError> {
+        let head = Self::parse_head(buf_reader, limits)?;
+        Self::read_body(buf_reader, head, limits)
+    }
+
+    /// Reads the request line and header fields.
+    pub fn parse_head<R: BufRead>(
+        buf_reader: &mut R,
+        limits: &Limits,
+    ) -> Result<Parts, ParseError> {
+        let read_request_line = |buf_reader: &mut R| {
+            limits::read_line(
+                buf_reader,
+                limits.request_line,
+                LimitExceeded::RequestLine(limits.request_line),
+                ParseError::RequestLine,
+            )
+        };
+        let mut request_line = read_request_line(buf_reader)?;
+        // An empty line before the request line is tolerated (RFC 9112
+        // section 2.2).
+        if request_line.is_empty() {
+            request_line = read_request_line(buf_reader)?;
+        }
+        let mut parts = Parts::parse(&request_line)?;
+        parts.headers = read_fields(buf_reader, limits)?;
+        Ok(parts)
+    }
+
+    /// Reads the body that follows `parts`, as framed by its headers.
+    pub fn read_body<R: BufRead>(
+        buf_reader: &mut R,
+        parts: Parts,
+        limits: &Limits,
+    ) -> Result<Request, ParseError> {
+        if parts.headers.contains_key("Transfer-Encoding") {
+            let transfer_encoding = parts
+                .headers
+                .get_all("Transfer-Encoding")
+                .collect::<Vec<_>>()
+                .join(", ");
+            // Only the chunked coding is supported, and for requests it must be
+            // the final one (RFC 9112 section 6.1).
+            if !transfer_encoding.trim().eq_ignore_ascii_case("chunked") {
+                return Err(ParseError::TransferCoding(transfer_encoding));
             }
-            if let Some((key, value)) = line.split_once(": ") {
-                parts.headers.insert(key.to_string(), value.to_string());
+            // A message with both is a request smuggling attempt more often
+            // than not (RFC 9112 section 6.3).
+            if parts.headers.contains_key("Content-Length") {
+                return Err(ParseError::ConflictingLength);
             }
+
+            let (buf, trailers) = chunked::decode(buf_reader, limits)?;
+            let mut request = Request::new(parts, Bytes::from(buf));
+            request.trailers = trailers;
+            return Ok(request);
+        }
+
+        let content_length = content_length(&parts.headers)?.unwrap_or(0);
+        if content_length > limits.body {
+            return Err(LimitExceeded::Body(limits.body).into());
         }
 
-        let content_length = parts
-            .headers
-            .get("Content-Length")
-            .and_then(|v| v.parse::<usize>().ok())
-            .unwrap_or(0);
+        let mut buf = Vec::new();
+        limits::read_body(buf_reader, content_length, &mut buf)?;
+        Ok(Request::new(parts, Bytes::from(buf)))
+    }
+}
+
+/// Reads header (or trailer) fields up to and including the empty line that
+/// ends them.
+pub(crate) fn read_fields<R: BufRead>(
+    buf_reader: &mut R,
+    limits: &Limits,
+) -> Result<HeaderMap, ParseError> {
+    let mut fields = HeaderMap::new();
+    let mut size = 0;
+    loop {
+        let line = limits::read_line(
+            buf_reader,
+            limits.header_line,
+            LimitExceeded::HeaderLine(limits.header_line),
+            ParseError::HeaderField,
+        )?;
+        if line.is_empty() {
+            return Ok(fields);
+        }
+        size += line.len();
+        if size > limits.header_size {
+            return Err(LimitExceeded::HeaderSize(limits.header_size).into());
+        }
+        if fields.len() == limits.header_count {
+            return Err(LimitExceeded::HeaderCount(limits.header_count).into());
+        }
+        // Obsolete line folding is rejected rather than unfolded (RFC 9112
+        // section 5.2).
+        if line.starts_with([' ', '\t']) {
+            return Err(ParseError::HeaderField(line));
+        }
+
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(ParseError::HeaderField(line));
+        };
+        fields.try_append(name, value)?;
+    }
+}
 
-        if content_length == 0 {
-            Ok(Request::new(parts, None))
+/// Parses `Content-Length`, which may be repeated, or be a list, only if all
+/// the values agree (RFC 9112 section 6.3).
+fn content_length(headers: &HeaderMap) -> Result<Option<u64>, ParseError> {
+    let joined = || {
+        headers
+            .get_all("Content-Length")
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut length = None;
+    for value in headers.get_all("Content-Length").flat_map(|v| v.split(',')) {
+        let value = value.trim();
+        let parsed = if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+            value.parse::<u64>().ok()
         } else {
-            let mut buf = vec![0u8; content_length];
-            buf_reader.read_exact(&mut buf)?;
-            match String::from_utf8(buf) {
-                Ok(body) => Ok(Request::new(parts, Some(body))),
-                Err(e) => {
-                    eprintln!("Failed to parse body as UTF-8: {}", e);
-                    Ok(Request::new(parts, None))
+            None
+        };
+        match (parsed, length) {
+            (None, _) => return Err(ParseError::ContentLength(joined())),
+            (Some(parsed), Some(length)) if parsed != length => {
+                return Err(ParseError::ContentLength(joined()));
+            }
+            (parsed, _) => length = parsed,
+        }
+    }
+    Ok(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Request, ParseError> {
+        Request::parse_request(&mut input.as_bytes(), &Limits::default())
+    }
+
+    fn parse_with(input: &str, limits: Limits) -> Result<Request, ParseError> {
+        Request::parse_request(&mut input.as_bytes(), &limits)
+    }
+
+    #[test]
+    fn reads_header_fields() {
+        let request = parse("GET / HTTP/1.1\r\nHost: a\r\nAccept:  x \r\naccept: y\n\r\n").unwrap();
+        assert_eq!(request.head.headers.get("host"), Some("a"));
+        assert_eq!(
+            request.head.headers.get_all("Accept").collect::<Vec<_>>(),
+            ["x", "y"]
+        );
+    }
+
+    #[test]
+    fn rejects_obsolete_line_folding() {
+        assert!(matches!(
+            parse("GET / HTTP/1.1\r\nX-A: a\r\n b\r\n\r\n"),
+            Err(ParseError::HeaderField(_))
+        ));
+        assert!(matches!(
+            parse("GET / HTTP/1.1\r\nX-A: a\r\n\tb\r\n\r\n"),
+            Err(ParseError::HeaderField(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_header_fields() {
+        assert!(matches!(
+            parse("GET / HTTP/1.1\r\nNo colon\r\n\r\n"),
+            Err(ParseError::HeaderField(_))
+        ));
+        assert!(matches!(
+            parse("GET / HTTP/1.1\r\nBad name: x\r\n\r\n"),
+            Err(ParseError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn attributes_invalid_utf8_to_the_line_it_is_on() {
+        let parse_bytes =
+            |input: &[u8]| Request::parse_request(&mut &input[..], &Limits::default());
+        assert!(matches!(
+            parse_bytes(b"GET /\xff HTTP/1.1\r\n\r\n"),
+            Err(ParseError::RequestLine(_))
+        ));
+        assert!(matches!(
+            parse_bytes(b"\r\nGET /\xff HTTP/1.1\r\n\r\n"),
+            Err(ParseError::RequestLine(_))
+        ));
+        assert!(matches!(
+            parse_bytes(b"GET / HTTP/1.1\r\nX-A: \xff\r\n\r\n"),
+            Err(ParseError::HeaderField(_))
+        ));
+    }
+
+    #[test]
+    fn enforces_head_limits() {
+        let limits = || Limits {
+            request_line: 16,
+            header_line: 16,
+            header_count: 2,
+            header_size: 24,
+            body: 4,
+        };
+        let cases = [
+            (
+                "GET /0123456789 HTTP/1.1\r\n\r\n",
+                LimitExceeded::RequestLine(16),
+            ),
+            (
+                "GET / HTTP/1.1\r\nX-Long: 0123456789\r\n\r\n",
+                LimitExceeded::HeaderLine(16),
+            ),
+            (
+                "GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n",
+                LimitExceeded::HeaderCount(2),
+            ),
+            (
+                "GET / HTTP/1.1\r\nA: 0123456789\r\nB: 0123456789\r\n\r\n",
+                LimitExceeded::HeaderSize(24),
+            ),
+        ];
+        for (input, expected) in cases {
+            match parse_with(input, limits()) {
+                Err(ParseError::TooLarge(limit)) => {
+                    assert_eq!(limit.to_string(), expected.to_string(), "{input:?}")
                 }
+                other => panic!("{input:?}: {other:?}"),
+            }
+        }
+        // Limits are inclusive.
+        assert!(parse_with("GET / HTTP/1.1\r\nX-Exact: 1234567\r\n\r\n", limits()).is_ok());
+    }
+
+    #[test]
+    fn enforces_the_body_limit_before_reading() {
+        let limits = Limits {
+            body: 4,
+            ..Limits::default()
+        };
+        // The body is refused on its announced length alone.
+        let result = parse_with(
+            "POST / HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n",
+            limits,
+        );
+        assert!(matches!(
+            result,
+            Err(ParseError::TooLarge(LimitExceeded::Body(4)))
+        ));
+    }
+
+    #[test]
+    fn reads_bodies() {
+        let request = parse("POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello").unwrap();
+        assert_eq!(&request.body[..], b"hello");
+        let request =
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n")
+                .unwrap();
+        assert_eq!(&request.body[..], b"hello");
+    }
+
+    #[test]
+    fn applies_persistence_rules() {
+        let keep_alive = |version: &str, connection: &[&str]| {
+            let mut parts = Parts::parse(&format!("GET / {version}")).unwrap();
+            for value in connection {
+                parts.headers.append("Connection", *value);
             }
+            parts.keep_alive()
+        };
+        assert!(keep_alive("HTTP/1.1", &[]));
+        assert!(keep_alive("HTTP/1.1", &["keep-alive"]));
+        assert!(!keep_alive("HTTP/1.1", &["close"]));
+        assert!(!keep_alive("HTTP/1.1", &["Close"]));
+        assert!(!keep_alive("HTTP/1.1", &["foo, close"]));
+        assert!(!keep_alive("HTTP/1.1", &["foo", " close "]));
+        assert!(!keep_alive("HTTP/1.0", &[]));
+        assert!(keep_alive("HTTP/1.0", &["keep-alive"]));
+        assert!(keep_alive("HTTP/1.0", &["Keep-Alive"]));
+        assert!(keep_alive("HTTP/1.0", &["foo,keep-alive"]));
+        assert!(!keep_alive("HTTP/1.0", &["keep-alive, close"]));
+        assert!(!keep_alive("HTTP/1.0", &["keep-alive-ish"]));
+    }
+
+    #[test]
+    fn parses_request_lines() {
+        let parts = Parts::parse("GET /a?b=c HTTP/1.1").unwrap();
+        assert_eq!(parts.method, "GET");
+        assert_eq!(parts.path, "/a?b=c");
+        assert!(parts.version == Version::HTTP_11);
+        assert!(Parts::parse("GET / HTTP/1.0").unwrap().version == Version::HTTP_10);
+        assert!(Parts::parse("GET / HTTP/1.9").unwrap().version == Version::HTTP_11);
+    }
+
+    #[test]
+    fn rejects_malformed_request_lines() {
+        for line in [
+            "",
+            "GET /",
+            "GET / HTTP/1.1 extra",
+            "GET  / HTTP/1.1",
+            "G(T / HTTP/1.1",
+            "GET /\x01 HTTP/1.1",
+            "GET / HTTP/1",
+            "GET / HTTP/1.10",
+            "GET / HTTPS/1.1",
+            "GET / http/1.1",
+        ] {
+            assert!(
+                matches!(Parts::parse(line), Err(ParseError::RequestLine(_))),
+                "{line:?}"
+            );
         }
     }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        for line in ["GET / HTTP/2.0", "GET / HTTP/0.9", "GET / HTTP/3.0"] {
+            assert!(
+                matches!(Parts::parse(line), Err(ParseError::Version(_))),
+                "{line:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn skips_one_leading_empty_line() {
+        assert!(parse("\r\nGET / HTTP/1.1\r\n\r\n").is_ok());
+        assert!(matches!(
+            parse("\r\n\r\nGET / HTTP/1.1\r\n\r\n"),
+            Err(ParseError::RequestLine(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_ambiguous_framing() {
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n"),
+            Err(ParseError::ConflictingLength)
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab"),
+            Err(ParseError::ContentLength(_))
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: +1\r\n\r\na"),
+            Err(ParseError::ContentLength(_))
+        ));
+        assert!(parse("POST / HTTP/1.1\r\nContent-Length: 1, 1\r\n\r\na").is_ok());
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n"),
+            Err(ParseError::TransferCoding(_))
+        ));
+    }
+
+    #[test]
+    fn reports_truncated_requests() {
+        for input in [
+            "GET / HTTP/1.1",
+            "GET / HTTP/1.1\r\nHost: a\r\n",
+            "POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nabc",
+        ] {
+            assert!(
+                matches!(parse(input), Err(ParseError::Truncated)),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn maps_errors_to_statuses() {
+        let status = |input: &str| parse(input).unwrap_err().status().as_u16();
+        assert_eq!(status("GET /\r\n\r\n"), 400);
+        assert_eq!(status("GET / HTTP/2.0\r\n\r\n"), 505);
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n"),
+            501
+        );
+        assert_eq!(status("GET / HTTP/1.1\r\n"), 400);
+        let timeout = ParseError::from(io::Error::from(ErrorKind::TimedOut));
+        assert_eq!(timeout.status().as_u16(), 408);
+    }
 }
@@ -1,6 +1,6 @@
-use std::{collections::HashMap, fmt::Display};
+use std::io::{self, Write};
 
-use crate::http::{status::StatusCode, version::Version};
+use crate::http::{body::Body, header::HeaderMap, status::StatusCode, version::Version};
 
 const DELIMITERS: &str = "\r\n";
 
@@ -8,7 +8,7 @@ const DELIMITERS: &str = "\r\n";
 pub struct Parts {
     pub status_code: StatusCode,
     pub version: Version,
-    pub headers: HashMap<String, String>,
+    pub headers: HeaderMap,
 }
 
 impl Parts {
@@ -16,7 +16,7 @@ impl Parts {
         Self {
             status_code,
             version,
-            headers: HashMap::new(),
+            headers: HeaderMap::new(),
         }
     }
 }
@@ -24,12 +24,15 @@ impl Parts {
 #[derive(Debug)]
 pub struct Response {
     pub head: Parts,
-    pub body: Option<Vec<u8>>,
+    pub body: Body,
 }
 
 impl Response {
-    pub fn new(head: Parts, body: Option<Vec<u8>>) -> Self {
-        Self { head, body }
+    pub fn new(head: Parts, body: impl Into<Body>) -> Self {
+        Self {
+            head,
+            body: body.into(),
+        }
     }
 
     pub fn to_http_headers_only(&self) -> String {
@@ -42,10 +45,49 @@ impl Response {
         );
 
         // Generate Headers
-        for (key, value) in &self.head.headers {
+        for (key, value) in self.head.headers.iter() {
             response.push_str(&format!("{}: {}{}", key, value, DELIMITERS));
         }
         response.push_str(DELIMITERS);
         response
     }
+
+    /// Writes the response to `w`, choosing the message framing from the body.
+    ///
+    /// Bodies of known length are sent with `Content-Length`. Otherwise the
+    /// chunked coding is used, except for HTTP/1.0 peers which do not support
+    /// it; there the body is delimited by closing the connection, and
+    /// `Connection: close` is set to say so.
+    ///
+    /// With `include_body` false, as for responses to `HEAD`, the framing
+    /// headers are still sent but the body is not.
+    pub fn write_to<W: Write>(&mut self, w: &mut W, include_body: bool) -> io::Result<()> {
+        let mut chunked = false;
+        // 1xx, 204 and 304 responses never carry a body (RFC 9110 section 6.4.1).
+        let status = self.head.status_code.as_u16();
+        let bodiless = status < 200 || status == 204 || status == 304;
+
+        if !bodiless && !self.head.headers.contains_key("Content-Length") {
+            match self.body.content_length() {
+                Some(len) => {
+                    self.head.headers.insert("Content-Length", len.to_string());
+                }
+                None if self.head.version >= Version::HTTP_11 => {
+                    self.head.headers.insert("Transfer-Encoding", "chunked");
+                    chunked = true;
+                }
+                None => {
+                    self.head.headers.insert("Connection", "close");
+                    self.head.headers.remove("Keep-Alive");
+                }
+            }
+        }
+
+        w.write_all(self.to_http_headers_only().as_bytes())?;
+        let body = std::mem::take(&mut self.body);
+        if include_body && !bodiless {
+            body.write_to(w, chunked)?;
+        }
+        w.flush()
+    }
 }
@@ -8,9 +8,31 @@ pub struct StatusCode(NonZeroU16);
 impl StatusCode {
     pub const OK: StatusCode = StatusCode(unsafe { NonZeroU16::new_unchecked(200) });
     pub const CREATED: StatusCode = StatusCode(unsafe { NonZeroU16::new_unchecked(201) });
+    pub const NO_CONTENT: StatusCode = StatusCode(unsafe { NonZeroU16::new_unchecked(204) });
+    pub const PARTIAL_CONTENT: StatusCode = StatusCode(unsafe { NonZeroU16::new_unchecked(206) });
+    pub const MOVED_PERMANENTLY: StatusCode = StatusCode(unsafe { NonZeroU16::new_unchecked(301) });
+    pub const NOT_MODIFIED: StatusCode = StatusCode(unsafe { NonZeroU16::new_unchecked(304) });
+    pub const BAD_REQUEST: StatusCode = StatusCode(unsafe { NonZeroU16::new_unchecked(400) });
+    pub const FORBIDDEN: StatusCode = StatusCode(unsafe { NonZeroU16::new_unchecked(403) });
     pub const NOT_FOUND: StatusCode = StatusCode(unsafe { NonZeroU16::new_unchecked(404) });
+    pub const METHOD_NOT_ALLOWED: StatusCode =
+        StatusCode(unsafe { NonZeroU16::new_unchecked(405) });
+    pub const REQUEST_TIMEOUT: StatusCode = StatusCode(unsafe { NonZeroU16::new_unchecked(408) });
+    pub const PRECONDITION_FAILED: StatusCode =
+        StatusCode(unsafe { NonZeroU16::new_unchecked(412) });
+    pub const PAYLOAD_TOO_LARGE: StatusCode = StatusCode(unsafe { NonZeroU16::new_unchecked(413) });
+    pub const URI_TOO_LONG: StatusCode = StatusCode(unsafe { NonZeroU16::new_unchecked(414) });
+    pub const RANGE_NOT_SATISFIABLE: StatusCode =
+        StatusCode(unsafe { NonZeroU16::new_unchecked(416) });
+    pub const REQUEST_HEADER_FIELDS_TOO_LARGE: StatusCode =
+        StatusCode(unsafe { NonZeroU16::new_unchecked(431) });
     pub const INTERNAL_SERVER_ERROR: StatusCode =
         StatusCode(unsafe { NonZeroU16::new_unchecked(500) });
+    pub const NOT_IMPLEMENTED: StatusCode = StatusCode(unsafe { NonZeroU16::new_unchecked(501) });
+    pub const SERVICE_UNAVAILABLE: StatusCode =
+        StatusCode(unsafe { NonZeroU16::new_unchecked(503) });
+    pub const HTTP_VERSION_NOT_SUPPORTED: StatusCode =
+        StatusCode(unsafe { NonZeroU16::new_unchecked(505) });
 
     pub fn from_u16(src: u16) -> Result<StatusCode, InvalidStatusCode> {
         if !(100..1000).contains(&src) {
@@ -23,7 +45,7 @@ impl StatusCode {
     }
 
     pub const fn as_u16(&self) -> u16 {
-        (*self).0.get()
+        self.0.get()
     }
 
     // pub fn as_str(&self) -> &str {
@@ -48,8 +70,24 @@ fn canonical_reason(num: u16) -> &'static str {
     match num {
         200 => "OK",
         201 => "Created",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
         404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        412 => "Precondition Failed",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        416 => "Range Not Satisfiable",
+        431 => "Request Header Fields Too Large",
         500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
         _ => "Unknown",
     }
 }
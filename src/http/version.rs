@@ -11,6 +11,7 @@ impl Version {
     pub const HTTP_3: Version = Version(Http::H3);
     pub const UNKNOWN: Version = Version(Http::__NonExhaustive);
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(src: &str) -> Version {
         match src {
             "HTTP/0.9" => Version::HTTP_09,
@@ -0,0 +1,10 @@
+pub mod config;
+pub mod handlers;
+pub mod http;
+pub mod logging;
+pub mod middleware;
+pub mod net;
+pub mod pool;
+pub mod route;
+pub mod shutdown;
+pub mod state;
@@ -0,0 +1,41 @@
+use std::{io::Write, thread, time::SystemTime};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::http::date;
+
+/// Writes log records to stderr, one line each, prefixed with the time, the
+/// level and the name of the thread that logged them.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let thread = thread::current();
+        let _ = writeln!(
+            std::io::stderr().lock(),
+            "[{} {:<5} {}] {}",
+            date::format(SystemTime::now()),
+            record.level(),
+            thread.name().unwrap_or("-"),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the stderr logger with the given maximum level.
+///
+/// Only the first call has any effect.
+pub fn init(level: LevelFilter) {
+    if log::set_logger(&StderrLogger).is_ok() {
+        log::set_max_level(level);
+    }
+}
@@ -1,11 +1,111 @@
-mod handlers;
-mod http;
-mod net;
-mod route;
+use std::{env, io, process, thread};
+
+use log::{info, warn};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+
+use codecrafters_http_server::{
+    config::{Command, Config, USAGE},
+    handlers::{self, FilesConfig},
+    logging,
+    middleware::{self, Compression, Layered},
+    net::Server,
+    route::Router,
+    shutdown::ShutdownHandle,
+};
 
 fn main() {
-    // You can use print statements as follows for debugging, they'll be visible when running tests.
-    println!("Logs from your program will appear here!");
+    let config = match Config::from_args(env::args().skip(1)) {
+        Ok(Command::Serve(config)) => *config,
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\nFor more information, try '--help'.", e);
+            process::exit(2);
+        }
+    };
+    logging::init(config.log_level);
+
+    let files = config.directory.clone().map(|directory| {
+        let mut files = FilesConfig::new(directory);
+        files.sniff = config.sniff_types;
+        if let Some(path) = &config.mime_types {
+            if let Err(e) = files.mime_types.load(path) {
+                eprintln!("error: failed to load {}: {}", path.display(), e);
+                process::exit(1);
+            }
+        }
+        files
+    });
+
+    let server = match Server::bind(router(&config, files), &config) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!(
+                "error: failed to listen on {}:{}: {}",
+                config.bind, config.port, e
+            );
+            process::exit(1);
+        }
+    };
+    if let Err(e) = handle_signals(server.shutdown_handle()) {
+        eprintln!("error: failed to install signal handlers: {}", e);
+        process::exit(1);
+    }
+    if let Err(e) = server.run() {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Shuts the server down gracefully on SIGINT or SIGTERM, and exits at once on
+/// a second signal.
+fn handle_signals(shutdown: ShutdownHandle) -> io::Result<()> {
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    thread::Builder::new()
+        .name("signals".to_string())
+        .spawn(move || {
+            let mut signals = signals.forever();
+            if let Some(signal) = signals.next() {
+                info!("Received signal {}, shutting down", signal);
+                shutdown.shutdown();
+            }
+            if signals.next().is_some() {
+                warn!("Received second signal, exiting immediately");
+                process::exit(130);
+            }
+        })?;
+    Ok(())
+}
 
-    net::run_server("127.0.0.1:4221");
+fn router(config: &Config, files: Option<FilesConfig>) -> Router {
+    let mut router = Router::new();
+    router.add_state(config.clone());
+    router.layer(middleware::logger);
+    router.layer(Compression::default());
+    if let Some(files) = files {
+        router.add_state(files);
+    }
+    router.add_route("GET", "/", handlers::root_handler);
+    // Echoed messages are short, but clients still expect them compressed.
+    router.add_route(
+        "GET",
+        "/echo/:msg",
+        Layered::new(handlers::echo_handler).layer(Compression::default().min_size(0)),
+    );
+    router.add_route("GET", "/user-agent", handlers::user_agent_handler);
+    router.add_route("GET", "/files/*path", handlers::files_handler);
+    router.add_route("POST", "/files/*path", handlers::post_file_handler);
+    router.add_route("PUT", "/files/*path", handlers::put_file_handler);
+    router.add_route("DELETE", "/files/*path", handlers::delete_file_handler);
+    router.add_route("PATCH", "/files/*path", handlers::patch_file_handler);
+    router
 }
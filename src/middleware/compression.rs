@@ -0,0 +1,384 @@
+use std::io::{self, Cursor, Read};
+
+use flate2::read::{GzEncoder, ZlibEncoder};
+use log::error;
+
+use crate::{
+    http::{
+        body::Body,
+        conditional::EntityTag,
+        header::HeaderMap,
+        request::Request,
+        response::{Parts, Response},
+        status::StatusCode,
+    },
+    middleware::{Middleware, Next},
+};
+
+/// A content coding the server can apply to response bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coding {
+    Brotli,
+    Zstd,
+    Gzip,
+    Deflate,
+}
+
+impl Coding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Coding::Brotli => "br",
+            Coding::Zstd => "zstd",
+            Coding::Gzip => "gzip",
+            Coding::Deflate => "deflate",
+        }
+    }
+
+    fn matches(&self, token: &str) -> bool {
+        token.eq_ignore_ascii_case(self.as_str())
+            || (*self == Coding::Gzip && token.eq_ignore_ascii_case("x-gzip"))
+    }
+
+    /// Wraps `reader` so that reading from it yields the encoded bytes.
+    fn encode(&self, reader: Box<dyn Read + Send>) -> io::Result<Box<dyn Read + Send>> {
+        Ok(match self {
+            Coding::Brotli => Box::new(brotli::CompressorReader::new(reader, 4096, 5, 22)),
+            Coding::Zstd => Box::new(zstd::stream::read::Encoder::new(reader, 3)?),
+            Coding::Gzip => Box::new(GzEncoder::new(reader, flate2::Compression::default())),
+            // The "deflate" coding is the zlib format (RFC 9110 section 8.4.1.2).
+            Coding::Deflate => Box::new(ZlibEncoder::new(reader, flate2::Compression::default())),
+        })
+    }
+}
+
+/// Compresses response bodies with the best coding the client accepts.
+///
+/// The coding is negotiated from `Accept-Encoding`, honoring q-values.
+/// Responses that are already encoded, partial, smaller than `min_size`, or of
+/// a content type that is compressed by nature are sent unchanged. Bodies of
+/// unknown length are compressed as they are streamed.
+pub struct Compression {
+    /// Supported codings, most preferred first. Used to break q-value ties.
+    pub codings: Vec<Coding>,
+    /// Bodies shorter than this many bytes are not worth compressing.
+    pub min_size: u64,
+}
+
+impl Compression {
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            codings: vec![Coding::Brotli, Coding::Zstd, Coding::Gzip, Coding::Deflate],
+            min_size: 256,
+        }
+    }
+}
+
+impl Middleware for Compression {
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response {
+        let mut response = next.run(request);
+        if response.head.status_code.as_u16() == 304 {
+            // A 304 carries the headers the full response would have had.
+            add_vary(&mut response.head.headers, "Accept-Encoding");
+            return response;
+        }
+        if !is_compressible(&response) {
+            return response;
+        }
+
+        // The representation now depends on Accept-Encoding, whether or not
+        // this particular response ends up compressed.
+        add_vary(&mut response.head.headers, "Accept-Encoding");
+
+        if response
+            .body
+            .content_length()
+            .is_some_and(|len| len < self.min_size)
+        {
+            return response;
+        }
+        let accept_encoding = request
+            .head
+            .headers
+            .get_all("Accept-Encoding")
+            .collect::<Vec<_>>()
+            .join(",");
+        let Some(coding) = negotiate(&accept_encoding, &self.codings) else {
+            return response;
+        };
+
+        match compress(std::mem::take(&mut response.body), coding) {
+            Ok(body) => {
+                response.body = body;
+                response
+                    .head
+                    .headers
+                    .insert("Content-Encoding", coding.as_str());
+                response.head.headers.remove("Content-Length");
+                weaken_etag(&mut response.head.headers);
+            }
+            Err(e) => {
+                error!("Failed to compress response: {}", e);
+                return Response::new(
+                    Parts::new(StatusCode::INTERNAL_SERVER_ERROR, response.head.version),
+                    None,
+                );
+            }
+        }
+        response
+    }
+}
+
+fn compress(body: Body, coding: Coding) -> io::Result<Body> {
+    match body {
+        Body::Empty => Ok(Body::Empty),
+        Body::Full(data) => {
+            let mut compressed = Vec::new();
+            coding
+                .encode(Box::new(Cursor::new(data)))?
+                .read_to_end(&mut compressed)?;
+            Ok(Body::Full(compressed))
+        }
+        Body::Reader { reader, .. } => Ok(Body::Reader {
+            reader: coding.encode(reader)?,
+            len: None,
+        }),
+    }
+}
+
+fn is_compressible(response: &Response) -> bool {
+    let status = response.head.status_code.as_u16();
+    let headers = &response.head.headers;
+
+    (200..300).contains(&status)
+        && status != 204
+        && status != 206
+        && !headers.contains_key("Content-Encoding")
+        && !headers.contains_key("Content-Range")
+        && !headers
+            .get("Content-Type")
+            .is_some_and(is_precompressed_type)
+}
+
+/// Returns whether bodies of `content_type` are typically compressed already.
+fn is_precompressed_type(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let Some((kind, subtype)) = essence.split_once('/') else {
+        return false;
+    };
+
+    match kind {
+        "image" => subtype != "svg+xml" && subtype != "bmp",
+        "audio" | "video" => true,
+        "font" => subtype == "woff" || subtype == "woff2",
+        "application" => matches!(
+            subtype,
+            "zip"
+                | "gzip"
+                | "x-gzip"
+                | "zstd"
+                | "x-bzip2"
+                | "x-xz"
+                | "x-7z-compressed"
+                | "x-rar-compressed"
+                | "vnd.rar"
+                | "pdf"
+                | "wasm"
+        ),
+        _ => false,
+    }
+}
+
+/// Picks the coding to use from an `Accept-Encoding` value.
+///
+/// Returns `None` when no supported coding has a non-zero q-value, or when
+/// `identity` is preferred over all of them.
+fn negotiate(accept_encoding: &str, supported: &[Coding]) -> Option<Coding> {
+    let preferences = accept_encoding
+        .split(',')
+        .filter_map(parse_preference)
+        .collect::<Vec<_>>();
+
+    let qvalue = |matches: &dyn Fn(&str) -> bool| -> Option<f32> {
+        preferences
+            .iter()
+            .find(|(token, _)| matches(token))
+            .or_else(|| preferences.iter().find(|(token, _)| *token == "*"))
+            .map(|(_, q)| *q)
+    };
+
+    let mut best: Option<(Coding, f32)> = None;
+    for &coding in supported {
+        let Some(q) = qvalue(&|token| coding.matches(token)) else {
+            continue;
+        };
+        if q > 0.0 && best.map_or(true, |(_, best_q)| q > best_q) {
+            best = Some((coding, q));
+        }
+    }
+
+    let (coding, q) = best?;
+    // identity is acceptable unless excluded, so it only wins if preferred.
+    let identity = qvalue(&|token| token.eq_ignore_ascii_case("identity")).unwrap_or(0.0);
+    (q >= identity).then_some(coding)
+}
+
+// Parses `coding [ ; q=qvalue ]`. Malformed entries are ignored.
+fn parse_preference(item: &str) -> Option<(&str, f32)> {
+    let mut params = item.split(';');
+    let token = params.next()?.trim();
+    if token.is_empty() {
+        return None;
+    }
+
+    let mut q = 1.0;
+    for param in params {
+        let (name, value) = param.split_once('=')?;
+        if name.trim().eq_ignore_ascii_case("q") {
+            q = value.trim().parse::<f32>().ok()?;
+            if !(0.0..=1.0).contains(&q) {
+                return None;
+            }
+        }
+    }
+    Some((token, q))
+}
+
+/// Marks a strong entity tag as weak: the encoded bytes differ from those of
+/// the identity representation the tag was computed for.
+fn weaken_etag(headers: &mut HeaderMap) {
+    if let Some(etag) = headers.get("ETag").and_then(EntityTag::parse) {
+        if !etag.weak {
+            headers.insert("ETag", EntityTag::weak(etag.tag).to_string());
+        }
+    }
+}
+
+/// Adds `field` to the response's `Vary` header unless it is already listed.
+fn add_vary(headers: &mut HeaderMap, field: &str) {
+    let existing = headers.get_all("Vary").collect::<Vec<_>>().join(", ");
+    let listed = existing
+        .split(',')
+        .map(str::trim)
+        .any(|v| v == "*" || v.eq_ignore_ascii_case(field));
+    if listed {
+        return;
+    }
+    if existing.is_empty() {
+        headers.insert("Vary", field);
+    } else {
+        headers.insert("Vary", format!("{}, {}", existing, field));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: &[Coding] = &[Coding::Brotli, Coding::Zstd, Coding::Gzip, Coding::Deflate];
+
+    #[test]
+    fn picks_the_highest_q_value() {
+        assert_eq!(negotiate("gzip", ALL), Some(Coding::Gzip));
+        assert_eq!(negotiate("gzip;q=0.5, deflate", ALL), Some(Coding::Deflate));
+        assert_eq!(negotiate("br;q=0.1, zstd;q=0.9", ALL), Some(Coding::Zstd));
+        assert_eq!(negotiate("x-gzip", ALL), Some(Coding::Gzip));
+        assert_eq!(negotiate("GZIP", ALL), Some(Coding::Gzip));
+    }
+
+    #[test]
+    fn breaks_ties_by_supported_order() {
+        assert_eq!(negotiate("gzip, br", ALL), Some(Coding::Brotli));
+        assert_eq!(
+            negotiate("gzip, br", &[Coding::Gzip, Coding::Brotli]),
+            Some(Coding::Gzip)
+        );
+    }
+
+    #[test]
+    fn excludes_codings_with_q_zero() {
+        assert_eq!(negotiate("gzip;q=0", ALL), None);
+        assert_eq!(negotiate("gzip;q=0.000", &[Coding::Gzip]), None);
+        assert_eq!(negotiate("gzip;q=0, deflate", ALL), Some(Coding::Deflate));
+        assert_eq!(negotiate("*, br;q=0, zstd;q=0", ALL), Some(Coding::Gzip));
+    }
+
+    #[test]
+    fn falls_back_to_the_wildcard() {
+        assert_eq!(negotiate("*", ALL), Some(Coding::Brotli));
+        assert_eq!(negotiate("*;q=0", ALL), None);
+        assert_eq!(negotiate("*;q=0, gzip", ALL), Some(Coding::Gzip));
+        assert_eq!(negotiate("*;q=0.5, br;q=0.1", ALL), Some(Coding::Zstd));
+    }
+
+    #[test]
+    fn honors_a_preference_for_identity() {
+        assert_eq!(negotiate("identity, gzip;q=0.5", ALL), None);
+        assert_eq!(negotiate("identity;q=0.5, gzip", ALL), Some(Coding::Gzip));
+        assert_eq!(negotiate("identity", ALL), None);
+        assert_eq!(negotiate("", ALL), None);
+    }
+
+    #[test]
+    fn ignores_malformed_q_values() {
+        for value in [
+            "gzip;q=2",
+            "gzip;q=-1",
+            "gzip;q=abc",
+            "gzip;q=NaN",
+            "gzip;q",
+        ] {
+            assert_eq!(negotiate(value, ALL), None, "{value:?}");
+        }
+        assert_eq!(negotiate("gzip;q=abc, deflate", ALL), Some(Coding::Deflate));
+    }
+
+    #[test]
+    fn parses_preferences() {
+        assert_eq!(parse_preference(" gzip "), Some(("gzip", 1.0)));
+        assert_eq!(parse_preference("gzip; Q=0.5"), Some(("gzip", 0.5)));
+        assert_eq!(parse_preference("gzip;level=1;q=0.2"), Some(("gzip", 0.2)));
+        assert_eq!(parse_preference(""), None);
+        assert_eq!(parse_preference(";q=1"), None);
+    }
+
+    #[test]
+    fn adds_vary_once() {
+        let mut headers = HeaderMap::new();
+        add_vary(&mut headers, "Accept-Encoding");
+        add_vary(&mut headers, "accept-encoding");
+        assert_eq!(headers.get("Vary"), Some("Accept-Encoding"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Vary", "Accept");
+        add_vary(&mut headers, "Accept-Encoding");
+        add_vary(&mut headers, "Accept-Encoding");
+        assert_eq!(headers.get("Vary"), Some("Accept, Accept-Encoding"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Vary", "*");
+        add_vary(&mut headers, "Accept-Encoding");
+        assert_eq!(headers.get("Vary"), Some("*"));
+    }
+
+    #[test]
+    fn weakens_strong_entity_tags() {
+        let mut headers = HeaderMap::new();
+        headers.insert("ETag", "\"abc\"");
+        weaken_etag(&mut headers);
+        assert_eq!(headers.get("ETag"), Some("W/\"abc\""));
+        weaken_etag(&mut headers);
+        assert_eq!(headers.get("ETag"), Some("W/\"abc\""));
+    }
+}
@@ -0,0 +1,100 @@
+use std::{collections::HashMap, time::Instant};
+
+use log::info;
+
+use crate::{
+    http::{request::Request, response::Response},
+    route::Handler,
+};
+
+pub mod compression;
+
+pub use compression::Compression;
+
+/// Code that runs around request handling.
+///
+/// A middleware receives the request and a [`Next`] that runs the rest of the
+/// chain. It may inspect the request, answer it directly without calling
+/// `next`, or call `next.run(request)` and adjust the response it returns.
+///
+/// Implemented for functions and closures with the matching signature.
+pub trait Middleware: Send + Sync + 'static {
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(&Request, Next<'_>) -> Response + Send + Sync + 'static,
+{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response {
+        self(request, next)
+    }
+}
+
+/// The remainder of a middleware chain.
+pub struct Next<'a> {
+    middleware: &'a [Box<dyn Middleware>],
+    endpoint: &'a dyn Fn(&Request) -> Response,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        middleware: &'a [Box<dyn Middleware>],
+        endpoint: &'a dyn Fn(&Request) -> Response,
+    ) -> Self {
+        Self {
+            middleware,
+            endpoint,
+        }
+    }
+
+    /// Runs the next middleware, or the endpoint once none are left.
+    pub fn run(self, request: &Request) -> Response {
+        match self.middleware.split_first() {
+            Some((first, rest)) => first.handle(request, Next::new(rest, self.endpoint)),
+            None => (self.endpoint)(request),
+        }
+    }
+}
+
+/// A handler wrapped in middleware that applies to its route only.
+pub struct Layered<H> {
+    handler: H,
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+impl<H: Handler> Layered<H> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Adds `middleware` inside the ones added before it.
+    pub fn layer(mut self, middleware: impl Middleware) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+}
+
+impl<H: Handler> Handler for Layered<H> {
+    fn call(&self, request: &Request, params: HashMap<String, String>) -> Response {
+        let endpoint = |request: &Request| self.handler.call(request, params.clone());
+        Next::new(&self.middleware, &endpoint).run(request)
+    }
+}
+
+/// Logs each request with its response status and handling time.
+pub fn logger(request: &Request, next: Next<'_>) -> Response {
+    let start = Instant::now();
+    let response = next.run(request);
+    info!(
+        "{} {} -> {} ({:?})",
+        request.head.method,
+        request.head.path,
+        response.head.status_code.as_str(),
+        start.elapsed()
+    );
+    response
+}
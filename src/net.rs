@@ -1,64 +1,298 @@
-use crate::handlers;
-use crate::http::request::Request;
+use log::{debug, error, info, warn};
+
+use crate::config::Config;
+use crate::http::request::{ParseError, Request};
+use crate::http::response::{Parts, Response};
+use crate::http::status::StatusCode;
+use crate::http::version::Version;
+use crate::pool::{self, ThreadPool};
 use crate::route::Router;
-use std::io::{self, Write};
-use std::{
-    net::{TcpListener, TcpStream},
-    thread,
-};
-
-pub fn run_server(addr: &str) {
-    let listener = TcpListener::bind(addr).unwrap();
-    println!("Listening on {}", addr);
-
-    for stream in listener.incoming() {
-        match stream {
-            Ok(stream) => {
-                println!("accepted new connection");
-                thread::spawn(move || loop {
-                    match handle_connection(&stream) {
-                        Ok(should_close) => {
-                            if should_close {
-                                println!("Closing connection");
-                                break;
-                            }
-                        }
+use crate::shutdown::{Connection, ShutdownHandle};
+use std::io::{self, BufRead, BufReader, BufWriter, ErrorKind, Read};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// An HTTP server bound to a listening socket.
+pub struct Server {
+    listener: TcpListener,
+    router: Router,
+    config: Config,
+    shutdown: ShutdownHandle,
+}
+
+impl Server {
+    /// Binds the configured address. Connections are not accepted until
+    /// [`run`](Server::run) is called.
+    pub fn bind(router: Router, config: &Config) -> io::Result<Server> {
+        let listener = TcpListener::bind(config.addr())?;
+        let shutdown = ShutdownHandle::new(listener.local_addr()?);
+        Ok(Server {
+            listener,
+            router,
+            config: config.clone(),
+            shutdown,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Returns a handle that stops the server once it is running.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Accepts and serves connections until the server is shut down, then
+    /// waits for open connections to finish, for up to the configured
+    /// shutdown timeout.
+    pub fn run(self) -> io::Result<()> {
+        info!("Listening on {}", self.listener.local_addr()?);
+
+        let router = Arc::new(self.router);
+        let config = self.config.clone();
+        let pool = ThreadPool::new(
+            self.config.workers,
+            self.config.queue_depth,
+            move |connection| serve_connection(connection, &router, &config),
+        );
+
+        for stream in self.listener.incoming() {
+            if self.shutdown.is_shutting_down() {
+                break;
+            }
+            match stream {
+                Ok(stream) => {
+                    if let Ok(peer) = stream.peer_addr() {
+                        debug!("Accepted connection from {}", peer);
+                    }
+                    let connection = match self.shutdown.track(stream) {
+                        Ok(connection) => connection,
                         Err(e) => {
-                            eprintln!("Error handling connection: {}", e);
-                            break;
+                            error!("Failed to track connection: {}", e);
+                            continue;
+                        }
+                    };
+                    if let Err(connection) = pool.try_submit(connection) {
+                        warn!("All workers are busy, rejecting connection");
+                        if let Err(e) = reject_connection(&connection.stream) {
+                            error!("Error rejecting connection: {}", e);
                         }
                     }
-                });
+                }
+                Err(e) => {
+                    error!("Failed to accept connection: {}", e);
+                }
+            }
+        }
+
+        info!("Shutting down");
+        // Refuse new connections while the open ones drain.
+        drop(self.listener);
+        if !self.shutdown.drain(self.config.shutdown_timeout) {
+            warn!("Shutdown timeout elapsed, closed remaining connections");
+        }
+        drop(pool);
+        info!("Server stopped");
+        Ok(())
+    }
+}
+
+/// Accepts connections on the configured address and serves them with
+/// `router`, until the server is shut down.
+pub fn run_server(router: Router, config: &Config) -> io::Result<()> {
+    Server::bind(router, config)?.run()
+}
+
+fn serve_connection(connection: Connection, router: &Router, config: &Config) {
+    if let Err(e) = connection
+        .stream
+        .set_write_timeout(Some(config.write_timeout))
+    {
+        error!("Failed to set write timeout: {}", e);
+        return;
+    }
+    // The reader lives as long as the connection so that bytes buffered past
+    // the end of one request are kept for the next.
+    let mut reader = BufReader::new(TimedReader::new(&connection.stream));
+    for served in 0..config.max_requests {
+        let remaining = config.max_requests - served - 1;
+        match handle_connection(&mut reader, &connection, router, config, remaining) {
+            Ok(should_close) => {
+                if should_close {
+                    debug!("Closing connection");
+                    break;
+                }
+            }
+            Err(e) if is_timeout(&e) => {
+                debug!("Connection timed out: {}", e);
+                break;
             }
             Err(e) => {
-                println!("error: {}", e);
+                error!("Error handling connection: {}", e);
+                break;
             }
         }
     }
 }
 
-fn handle_connection(mut stream: &TcpStream) -> io::Result<bool> {
-    let request = Request::parse_request(stream)?;
-    println!("{:?}", request);
+/// Reads from a stream with a timeout that is either a deadline for the
+/// whole of what is being read, or a limit on each individual read.
+struct TimedReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Option<Instant>,
+}
 
-    let mut router = Router::new();
-    router.add_route("GET", "/", handlers::root_handler);
-    router.add_route("GET", "/echo/:msg", handlers::echo_handler);
-    router.add_route("GET", "/user-agent", handlers::user_agent_handler);
-    router.add_route("GET", "/files/:filename", handlers::files_handler);
-    router.add_route("POST", "/files/:filename", handlers::post_file_handler);
+impl<'a> TimedReader<'a> {
+    fn new(stream: &'a TcpStream) -> Self {
+        Self {
+            stream,
+            deadline: None,
+        }
+    }
+
+    /// Fails reads once `timeout` has elapsed from now. A timeout too long to
+    /// represent as a deadline means reads never time out.
+    fn set_deadline(&mut self, timeout: Duration) -> io::Result<()> {
+        self.deadline = Instant::now().checked_add(timeout);
+        if self.deadline.is_none() {
+            self.stream.set_read_timeout(None)?;
+        }
+        Ok(())
+    }
+
+    /// Fails reads that see no data for `timeout`, however long reading takes
+    /// overall.
+    fn set_idle_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.deadline = None;
+        self.stream.set_read_timeout(Some(timeout))
+    }
+}
 
-    let response = router.route(&request);
-    println!("{:?}", response);
+impl Read for TimedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(ErrorKind::TimedOut, "read deadline elapsed"));
+            }
+            self.stream.set_read_timeout(Some(remaining))?;
+        }
+        self.stream.read(buf)
+    }
+}
+
+/// Socket timeouts surface as `WouldBlock` on some platforms and `TimedOut`
+/// on others.
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// Answers a connection with 503 Service Unavailable without reading its
+/// request, when it could not be queued or the server is shutting down.
+fn reject_connection(stream: &TcpStream) -> io::Result<()> {
+    let mut head = Parts::new(StatusCode::SERVICE_UNAVAILABLE, Version::HTTP_11);
+    head.headers.insert("Connection", "close");
+    head.headers.insert("Retry-After", "1");
+    Response::new(head, None).write_to(&mut BufWriter::new(stream), true)
+}
+
+/// Serves one request. `remaining` is how many more the connection may carry
+/// after this one. Returns whether the connection should be closed.
+fn handle_connection(
+    reader: &mut BufReader<TimedReader<'_>>,
+    connection: &Connection,
+    router: &Router,
+    config: &Config,
+    remaining: usize,
+) -> io::Result<bool> {
+    let stream = &connection.stream;
+    if !connection.idle() {
+        return Ok(true);
+    }
+    // Idle connections are closed without a response once the keep-alive
+    // timeout elapses; the client is not waiting for anything.
+    reader.get_mut().set_deadline(config.keep_alive_timeout)?;
+    if reader.fill_buf()?.is_empty() {
+        // The client closed the connection between requests, or the server
+        // is shutting down.
+        return Ok(true);
+    }
+    if !connection.busy() {
+        // The shutdown caught the request as it started arriving, and may
+        // already have cut off the rest of it; the client can retry.
+        debug!("Shutting down, rejecting request");
+        reject_connection(stream)?;
+        return Ok(true);
+    }
+
+    // The whole head must arrive in time, so that a client trickling it in
+    // byte by byte cannot hold on to a worker.
+    reader.get_mut().set_deadline(config.header_timeout)?;
+    let request = Request::parse_head(reader, &config.limits).and_then(|head| {
+        reader.get_mut().set_idle_timeout(config.body_timeout)?;
+        Request::read_body(reader, head, &config.limits)
+    });
+    let mut request = match request {
+        Ok(request) => request,
+        Err(ParseError::Io(e)) if !is_timeout(&e) => return Err(e),
+        Err(e) => {
+            if let ParseError::Io(_) = e {
+                debug!("Timed out reading request");
+            } else {
+                warn!("Rejecting request: {}", e);
+            }
+            // The framing is unknown, so the connection cannot be reused.
+            let mut head = Parts::new(e.status(), Version::HTTP_11);
+            head.headers.insert("Connection", "close");
+            Response::new(head, None).write_to(&mut BufWriter::new(stream), true)?;
+            return Ok(true);
+        }
+    };
+
+    // A panicking handler costs its client a 500, not the worker. The
+    // connection is closed, as whatever the handler shares may be left
+    // inconsistent.
+    let (mut response, panicked) =
+        match panic::catch_unwind(AssertUnwindSafe(|| router.route(&mut request))) {
+            Ok(response) => (response, false),
+            Err(payload) => {
+                error!("Handler panicked: {}", pool::panic_message(&*payload));
+                let head = Parts::new(StatusCode::INTERNAL_SERVER_ERROR, request.head.version);
+                (Response::new(head, None), true)
+            }
+        };
+    // Persistence is up to the server, whatever the handler says.
+    response.head.headers.remove("Connection");
+    response.head.headers.remove("Keep-Alive");
+    let keep_alive =
+        !panicked && request.head.keep_alive() && remaining > 0 && !connection.is_shutting_down();
+    if keep_alive {
+        // HTTP/1.1 connections persist by default; HTTP/1.0 ones must be told.
+        if request.head.version < Version::HTTP_11 {
+            response.head.headers.insert("Connection", "keep-alive");
+        }
+        response.head.headers.insert(
+            "Keep-Alive",
+            format!(
+                "timeout={}, max={}",
+                config.keep_alive_timeout.as_secs(),
+                remaining
+            ),
+        );
+    } else {
+        response.head.headers.insert("Connection", "close");
+    }
 
-    stream.write_all(response.to_http_headers_only().as_bytes())?;
-    stream.write_all(&response.body.unwrap_or_default())?;
+    response.write_to(&mut BufWriter::new(stream), request.head.method != "HEAD")?;
 
     let should_close = response
         .head
         .headers
         .get("Connection")
-        .is_some_and(|conn| conn.to_lowercase() == "close");
+        .is_some_and(|conn| conn.eq_ignore_ascii_case("close"));
 
     Ok(should_close)
 }
@@ -0,0 +1,134 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use log::error;
+
+/// A fixed set of worker threads that process items from a bounded queue.
+pub struct ThreadPool<T> {
+    shared: Arc<Shared<T>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+struct Shared<T> {
+    queue: Mutex<Queue<T>>,
+    available: Condvar,
+    capacity: usize,
+}
+
+struct Queue<T> {
+    items: VecDeque<T>,
+    /// Workers waiting for an item.
+    idle: usize,
+    closed: bool,
+}
+
+impl<T: Send + 'static> ThreadPool<T> {
+    /// Starts `workers` threads that call `handler` on each submitted item.
+    /// At most `queue_depth` items wait for a free worker; with a depth of
+    /// zero, items are only accepted while a worker is idle.
+    ///
+    /// A panic in `handler` is logged and does not take its worker down.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `workers` is zero.
+    pub fn new<F>(workers: usize, queue_depth: usize, handler: F) -> ThreadPool<T>
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        assert!(workers > 0, "thread pool needs at least one worker");
+
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                items: VecDeque::new(),
+                idle: 0,
+                closed: false,
+            }),
+            available: Condvar::new(),
+            capacity: queue_depth,
+        });
+        let handler = Arc::new(handler);
+
+        let workers = (0..workers)
+            .map(|id| {
+                let shared = Arc::clone(&shared);
+                let handler = Arc::clone(&handler);
+                thread::Builder::new()
+                    .name(format!("worker-{}", id))
+                    .spawn(move || {
+                        while let Some(item) = shared.next() {
+                            if let Err(payload) =
+                                panic::catch_unwind(AssertUnwindSafe(|| handler(item)))
+                            {
+                                error!("Worker panicked: {}", panic_message(&*payload));
+                            }
+                        }
+                    })
+                    .expect("failed to spawn worker thread")
+            })
+            .collect();
+
+        ThreadPool { shared, workers }
+    }
+
+    /// Queues `item` for a worker.
+    ///
+    /// Each queued item takes an idle worker or, failing that, a queue slot.
+    /// If there are none left the item is handed back so the caller can reject
+    /// the work instead of waiting for a free slot.
+    pub fn try_submit(&self, item: T) -> Result<(), T> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.items.len() >= self.shared.capacity.saturating_add(queue.idle) {
+            return Err(item);
+        }
+        queue.items.push_back(item);
+        drop(queue);
+        self.shared.available.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Shared<T> {
+    /// Blocks until an item is available, or returns `None` once the pool is
+    /// closed and the queue has been drained.
+    fn next(&self) -> Option<T> {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if let Some(item) = queue.items.pop_front() {
+                return Some(item);
+            }
+            if queue.closed {
+                return None;
+            }
+            queue.idle += 1;
+            queue = self.available.wait(queue).unwrap();
+            queue.idle -= 1;
+        }
+    }
+}
+
+/// Extracts the message from a panic payload, which is a `&str` or `String`
+/// unless the panic was raised with some other value.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic payload")
+}
+
+impl<T> Drop for ThreadPool<T> {
+    /// Lets the workers finish the queued items, then waits for them to exit.
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.available.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
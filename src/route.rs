@@ -1,67 +1,204 @@
-use std::{collections::HashMap, path};
+use std::collections::{BTreeSet, HashMap};
 
-use crate::http::{
-    request::Request,
-    response::{Parts, Response},
-    status::StatusCode,
+use crate::{
+    http::{
+        request::Request,
+        response::{Parts, Response},
+        status::StatusCode,
+    },
+    middleware::{Middleware, Next},
+    state::State,
 };
 
-type Handler = fn(&Request, HashMap<String, String>) -> Response;
+/// Produces the response to a routed request.
+///
+/// Implemented for functions and closures taking the request and the path
+/// parameters, and can be implemented by structs that carry their own
+/// configuration.
+pub trait Handler: Send + Sync + 'static {
+    fn call(&self, request: &Request, params: HashMap<String, String>) -> Response;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&Request, HashMap<String, String>) -> Response + Send + Sync + 'static,
+{
+    fn call(&self, request: &Request, params: HashMap<String, String>) -> Response {
+        self(request, params)
+    }
+}
 
+type Methods = HashMap<String, Box<dyn Handler>>;
+
+/// Routes requests by method and path.
+///
+/// Path patterns are made of `/`-separated segments. A segment is either
+/// static text, a `:name` parameter matching any single segment, or a final
+/// `*name` wildcard matching the rest of the path. When several patterns match
+/// a path, static segments take precedence over parameters, and parameters
+/// over wildcards, regardless of the order in which routes were added.
 #[derive(Default)]
 pub struct Router {
-    routes: HashMap<(String, String), Handler>,
+    root: Node,
+    state: State,
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+#[derive(Default)]
+struct Node {
+    handlers: Methods,
+    statics: HashMap<String, Node>,
+    param: Option<(String, Box<Node>)>,
+    wildcard: Option<(String, Methods)>,
 }
 
 impl Router {
     pub fn new() -> Self {
         Self {
-            routes: HashMap::new(),
+            root: Node::default(),
+            state: State::new(),
+            middleware: Vec::new(),
         }
     }
 
-    pub fn add_route(&mut self, method: &str, path_pattern: &str, handler: Handler) {
-        self.routes
-            .insert((method.to_string(), path_pattern.to_string()), handler);
+    /// Wraps every request this router handles, including those that end in
+    /// 404 or 405, in `middleware`. Middleware added later runs inside
+    /// middleware added earlier.
+    pub fn layer(&mut self, middleware: impl Middleware) {
+        self.middleware.push(Box::new(middleware));
     }
 
-    pub fn route(&self, request: &Request) -> Response {
-        for ((method, path_pattern), handler) in &self.routes {
-            if &request.head.method == method {
-                if let Some(params) =
-                    Self::match_and_extract_params(&request.head.path, path_pattern)
-                {
-                    return handler(request, params);
-                }
+    /// Registers a shared value that handlers can read from `request.state`.
+    pub fn add_state<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.state.insert(value);
+    }
+
+    /// Registers `handler` for `method` requests matching `path_pattern`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a wildcard is not the last segment of the pattern, or if a
+    /// parameter or wildcard is named differently from one already registered
+    /// at the same position.
+    pub fn add_route(&mut self, method: &str, path_pattern: &str, handler: impl Handler) {
+        let handler: Box<dyn Handler> = Box::new(handler);
+        let segments = split_path(path_pattern);
+        let mut node = &mut self.root;
+
+        for (i, segment) in segments.iter().enumerate() {
+            if let Some(name) = segment.strip_prefix('*') {
+                assert!(
+                    i == segments.len() - 1,
+                    "wildcard must be the last segment in {:?}",
+                    path_pattern
+                );
+                let (existing, handlers) = node
+                    .wildcard
+                    .get_or_insert_with(|| (name.to_string(), HashMap::new()));
+                assert_eq!(
+                    existing, name,
+                    "conflicting wildcard names in {:?}",
+                    path_pattern
+                );
+                handlers.insert(method.to_string(), handler);
+                return;
             }
+
+            node = match segment.strip_prefix(':') {
+                Some(name) => {
+                    let (existing, child) = node
+                        .param
+                        .get_or_insert_with(|| (name.to_string(), Box::default()));
+                    assert_eq!(
+                        existing, name,
+                        "conflicting parameter names in {:?}",
+                        path_pattern
+                    );
+                    child
+                }
+                None => node.statics.entry(segment.to_string()).or_default(),
+            };
         }
-        Self::not_found(request)
-    }
-
-    fn match_and_extract_params(path: &str, pattern: &str) -> Option<HashMap<String, String>> {
-        let mut params = HashMap::new();
-        let path_parts = path
-            .trim_matches(path::MAIN_SEPARATOR)
-            .split(path::MAIN_SEPARATOR)
-            .collect::<Vec<&str>>();
-        let pattern_parts = pattern
-            .trim_matches(path::MAIN_SEPARATOR)
-            .split(path::MAIN_SEPARATOR)
-            .collect::<Vec<&str>>();
-
-        if path_parts.len() != pattern_parts.len() {
-            return None;
+
+        node.handlers.insert(method.to_string(), handler);
+    }
+
+    /// Dispatches `request` to the matching handler.
+    ///
+    /// Unless handlers are registered for them, `HEAD` requests are served by
+    /// the `GET` handler (the body is dropped when the response is written) and
+    /// `OPTIONS` requests are answered with the allowed methods. The router's
+    /// state is attached to the request before it reaches the middleware.
+    pub fn route(&self, request: &mut Request) -> Response {
+        request.state = self.state.clone();
+        let endpoint = |request: &Request| self.dispatch(request);
+        Next::new(&self.middleware, &endpoint).run(request)
+    }
+
+    fn dispatch(&self, request: &Request) -> Response {
+        let path = request.head.path.split('?').next().unwrap_or_default();
+        let method = request.head.method.as_str();
+
+        if let Some((handler, params)) = self
+            .find(path, method)
+            .or_else(|| (method == "HEAD").then(|| self.find(path, "GET")).flatten())
+        {
+            return handler.call(request, params);
         }
 
-        for (p_part, pat_part) in path_parts.iter().zip(pattern_parts.iter()) {
-            if pat_part.starts_with(":") {
-                let key = pat_part.trim_start_matches(":").to_string();
-                params.insert(key, p_part.to_string());
-            } else if p_part != pat_part {
-                return None;
-            }
+        if method == "OPTIONS" && path == "*" {
+            return Self::options(request, &self.root.all_methods());
         }
-        Some(params)
+        let allowed = self.allowed_methods(path);
+        if allowed.is_empty() {
+            Self::not_found(request)
+        } else if method == "OPTIONS" {
+            Self::options(request, &allowed)
+        } else {
+            Self::method_not_allowed(request, &allowed)
+        }
+    }
+
+    fn find(&self, path: &str, method: &str) -> Option<(&dyn Handler, HashMap<String, String>)> {
+        let mut found = None;
+        self.root.visit(
+            &split_path(path),
+            &mut Vec::new(),
+            &mut |handlers, params| match handlers.get(method) {
+                Some(handler) => {
+                    found = Some((handler.as_ref(), params.iter().cloned().collect()));
+                    true
+                }
+                None => false,
+            },
+        );
+        found
+    }
+
+    /// Returns the methods that can be used with `path`.
+    fn allowed_methods(&self, path: &str) -> BTreeSet<&str> {
+        let mut allowed = BTreeSet::new();
+        self.root
+            .visit(&split_path(path), &mut Vec::new(), &mut |handlers, _| {
+                allowed.extend(handlers.keys().map(String::as_str));
+                false
+            });
+        with_implied_methods(allowed)
+    }
+
+    fn options(request: &Request, allowed: &BTreeSet<&str>) -> Response {
+        let mut head = Parts::new(StatusCode::NO_CONTENT, request.head.version);
+        head.headers.insert("Allow", join_methods(allowed));
+        Response::new(head, None)
+    }
+
+    fn method_not_allowed(request: &Request, allowed: &BTreeSet<&str>) -> Response {
+        let mut head = Parts::new(StatusCode::METHOD_NOT_ALLOWED, request.head.version);
+        head.headers.insert("Allow", join_methods(allowed));
+        Response::new(
+            head,
+            Some(StatusCode::METHOD_NOT_ALLOWED.canonical_reason().into()),
+        )
     }
 
     fn not_found(request: &Request) -> Response {
@@ -71,3 +208,335 @@ impl Router {
         )
     }
 }
+
+impl Node {
+    /// Returns the methods registered anywhere in the tree.
+    fn all_methods(&self) -> BTreeSet<&str> {
+        let mut methods = BTreeSet::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            methods.extend(node.handlers.keys().map(String::as_str));
+            if let Some((_, handlers)) = &node.wildcard {
+                methods.extend(handlers.keys().map(String::as_str));
+            }
+            stack.extend(node.statics.values());
+            if let Some((_, child)) = &node.param {
+                stack.push(child);
+            }
+        }
+        with_implied_methods(methods)
+    }
+
+    /// Calls `f` with the handlers and parameters of every route matching
+    /// `segments`, in precedence order, until it returns true.
+    fn visit<'a, F>(
+        &'a self,
+        segments: &[&str],
+        params: &mut Vec<(String, String)>,
+        f: &mut F,
+    ) -> bool
+    where
+        F: FnMut(&'a Methods, &[(String, String)]) -> bool,
+    {
+        let Some((segment, rest)) = segments.split_first() else {
+            if !self.handlers.is_empty() && f(&self.handlers, params) {
+                return true;
+            }
+            return self.visit_wildcard(segments, params, f);
+        };
+
+        if let Some(child) = self.statics.get(*segment) {
+            if child.visit(rest, params, f) {
+                return true;
+            }
+        }
+
+        if let Some((name, child)) = &self.param {
+            params.push((name.clone(), segment.to_string()));
+            if child.visit(rest, params, f) {
+                return true;
+            }
+            params.pop();
+        }
+
+        self.visit_wildcard(segments, params, f)
+    }
+
+    fn visit_wildcard<'a, F>(
+        &'a self,
+        segments: &[&str],
+        params: &mut Vec<(String, String)>,
+        f: &mut F,
+    ) -> bool
+    where
+        F: FnMut(&'a Methods, &[(String, String)]) -> bool,
+    {
+        let Some((name, handlers)) = &self.wildcard else {
+            return false;
+        };
+        params.push((name.clone(), segments.join("/")));
+        if f(handlers, params) {
+            return true;
+        }
+        params.pop();
+        false
+    }
+}
+
+/// Adds the methods the router answers on its own: `HEAD` wherever `GET` is
+/// registered, and `OPTIONS` for every known path.
+fn with_implied_methods(mut methods: BTreeSet<&str>) -> BTreeSet<&str> {
+    if methods.is_empty() {
+        return methods;
+    }
+    if methods.contains("GET") {
+        methods.insert("HEAD");
+    }
+    methods.insert("OPTIONS");
+    methods
+}
+
+fn join_methods(methods: &BTreeSet<&str>) -> String {
+    methods.iter().copied().collect::<Vec<_>>().join(", ")
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        return Vec::new();
+    }
+    path.split('/').collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::http::request;
+
+    /// A handler that names itself and the parameters it received in
+    /// response headers.
+    fn tagged(name: &'static str) -> impl Handler {
+        move |request: &Request, params: HashMap<String, String>| {
+            let mut params = params.into_iter().collect::<Vec<_>>();
+            params.sort();
+            let params = params
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect::<Vec<_>>();
+            let mut head = Parts::new(StatusCode::OK, request.head.version);
+            head.headers.insert("X-Handler", name);
+            head.headers.insert("X-Params", params.join("&"));
+            Response::new(head, None)
+        }
+    }
+
+    fn send(router: &Router, method: &str, path: &str) -> Response {
+        let line = format!("{} {} HTTP/1.1", method, path);
+        let mut request = Request::new(request::Parts::parse(&line).unwrap(), Bytes::new());
+        router.route(&mut request)
+    }
+
+    fn handler_for(router: &Router, path: &str) -> Option<String> {
+        let response = send(router, "GET", path);
+        response.head.headers.get("X-Handler").map(str::to_string)
+    }
+
+    fn params_for(router: &Router, path: &str) -> String {
+        let response = send(router, "GET", path);
+        response.head.headers.get("X-Params").unwrap().to_string()
+    }
+
+    fn files_router(order: &[usize]) -> Router {
+        let routes = [
+            ("/files/index", "static"),
+            ("/files/:name", "param"),
+            ("/files/*path", "wildcard"),
+        ];
+        let mut router = Router::new();
+        for &i in order {
+            let (pattern, name) = routes[i];
+            router.add_route("GET", pattern, tagged(name));
+        }
+        router
+    }
+
+    #[test]
+    fn precedence_does_not_depend_on_registration_order() {
+        let orders = [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ];
+        for order in orders {
+            let router = files_router(&order);
+            assert_eq!(
+                handler_for(&router, "/files/index").as_deref(),
+                Some("static")
+            );
+            assert_eq!(
+                handler_for(&router, "/files/other").as_deref(),
+                Some("param")
+            );
+            assert_eq!(
+                handler_for(&router, "/files/a/b").as_deref(),
+                Some("wildcard")
+            );
+        }
+    }
+
+    #[test]
+    fn extracts_parameters() {
+        let router = files_router(&[0, 1, 2]);
+        assert_eq!(params_for(&router, "/files/other"), "name=other");
+        assert_eq!(params_for(&router, "/files/a/b/c"), "path=a/b/c");
+        assert_eq!(params_for(&router, "/files/index?x=1"), "");
+    }
+
+    #[test]
+    fn backtracks_from_dead_end_static_branches() {
+        let mut router = Router::new();
+        router.add_route("GET", "/users/me/settings", tagged("settings"));
+        router.add_route("GET", "/users/:id/posts", tagged("posts"));
+        router.add_route("GET", "/users/*rest", tagged("rest"));
+
+        // "me" takes the static branch first, which has no "posts" below it.
+        assert_eq!(
+            handler_for(&router, "/users/me/posts").as_deref(),
+            Some("posts")
+        );
+        assert_eq!(params_for(&router, "/users/me/posts"), "id=me");
+        assert_eq!(
+            handler_for(&router, "/users/me/settings").as_deref(),
+            Some("settings")
+        );
+        // Neither branch matches, so the wildcard does.
+        assert_eq!(
+            handler_for(&router, "/users/me/other").as_deref(),
+            Some("rest")
+        );
+        assert_eq!(params_for(&router, "/users/me/other"), "rest=me/other");
+    }
+
+    #[test]
+    fn backtracks_on_method() {
+        let mut router = Router::new();
+        router.add_route("POST", "/files/index", tagged("static"));
+        router.add_route("GET", "/files/:name", tagged("param"));
+        assert_eq!(
+            handler_for(&router, "/files/index").as_deref(),
+            Some("param")
+        );
+    }
+
+    #[test]
+    fn matches_the_root_and_ignores_extra_slashes() {
+        let mut router = Router::new();
+        router.add_route("GET", "/", tagged("root"));
+        router.add_route("GET", "/a/b", tagged("ab"));
+        assert_eq!(handler_for(&router, "/").as_deref(), Some("root"));
+        assert_eq!(handler_for(&router, "/a/b/").as_deref(), Some("ab"));
+        assert_eq!(handler_for(&router, "/a"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "wildcard must be the last segment")]
+    fn rejects_wildcards_before_the_end() {
+        Router::new().add_route("GET", "/*rest/x", tagged("bad"));
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicting parameter names")]
+    fn rejects_conflicting_parameter_names() {
+        let mut router = Router::new();
+        router.add_route("GET", "/files/:name", tagged("a"));
+        router.add_route("PUT", "/files/:id", tagged("b"));
+    }
+
+    fn allow(response: &Response) -> &str {
+        response.head.headers.get("Allow").unwrap()
+    }
+
+    #[test]
+    fn answers_405_with_the_allowed_methods() {
+        let mut router = Router::new();
+        router.add_route("GET", "/files/*path", tagged("get"));
+        router.add_route("POST", "/files/*path", tagged("post"));
+        router.add_route("DELETE", "/files/:name", tagged("delete"));
+
+        let response = send(&router, "PUT", "/files/a");
+        assert_eq!(response.head.status_code.as_u16(), 405);
+        assert_eq!(allow(&response), "DELETE, GET, HEAD, OPTIONS, POST");
+
+        // Only the wildcard routes match a deeper path.
+        let response = send(&router, "PUT", "/files/a/b");
+        assert_eq!(allow(&response), "GET, HEAD, OPTIONS, POST");
+    }
+
+    #[test]
+    fn answers_404_when_no_route_matches_the_path() {
+        let mut router = Router::new();
+        router.add_route("GET", "/a", tagged("a"));
+        let response = send(&router, "PUT", "/b");
+        assert_eq!(response.head.status_code.as_u16(), 404);
+        assert!(!response.head.headers.contains_key("Allow"));
+    }
+
+    #[test]
+    fn omits_head_without_get() {
+        let mut router = Router::new();
+        router.add_route("POST", "/a", tagged("post"));
+        let response = send(&router, "GET", "/a");
+        assert_eq!(response.head.status_code.as_u16(), 405);
+        assert_eq!(allow(&response), "OPTIONS, POST");
+    }
+
+    #[test]
+    fn serves_head_with_the_get_handler() {
+        let mut router = Router::new();
+        router.add_route("GET", "/a/:id", tagged("get"));
+        let response = send(&router, "HEAD", "/a/1");
+        assert_eq!(response.head.headers.get("X-Handler"), Some("get"));
+        assert_eq!(response.head.headers.get("X-Params"), Some("id=1"));
+    }
+
+    #[test]
+    fn explicit_head_and_options_handlers_win() {
+        let mut router = Router::new();
+        router.add_route("GET", "/a", tagged("get"));
+        router.add_route("HEAD", "/a", tagged("head"));
+        router.add_route("OPTIONS", "/a", tagged("options"));
+        let response = send(&router, "HEAD", "/a");
+        assert_eq!(response.head.headers.get("X-Handler"), Some("head"));
+        let response = send(&router, "OPTIONS", "/a");
+        assert_eq!(response.head.headers.get("X-Handler"), Some("options"));
+    }
+
+    #[test]
+    fn answers_options_with_the_allowed_methods() {
+        let mut router = Router::new();
+        router.add_route("GET", "/a", tagged("get"));
+        router.add_route("PUT", "/a", tagged("put"));
+        let response = send(&router, "OPTIONS", "/a");
+        assert_eq!(response.head.status_code.as_u16(), 204);
+        assert_eq!(allow(&response), "GET, HEAD, OPTIONS, PUT");
+
+        let response = send(&router, "OPTIONS", "/missing");
+        assert_eq!(response.head.status_code.as_u16(), 404);
+    }
+
+    #[test]
+    fn answers_options_star_with_every_method() {
+        let mut router = Router::new();
+        router.add_route("GET", "/", tagged("root"));
+        router.add_route("POST", "/files/*path", tagged("post"));
+        router.add_route("DELETE", "/files/:name", tagged("delete"));
+        let response = send(&router, "OPTIONS", "*");
+        assert_eq!(response.head.status_code.as_u16(), 204);
+        assert_eq!(allow(&response), "DELETE, GET, HEAD, OPTIONS, POST");
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpStream},
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+/// Stops a running [`Server`](crate::net::Server) gracefully.
+///
+/// Shutting down stops the server from accepting connections, closes the
+/// connections that are waiting for a request, and lets the others finish the
+/// request they are serving before closing them too.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled whenever a tracked connection closes.
+    closed: Condvar,
+    /// Where to connect to wake up the accept loop.
+    wake_addr: SocketAddr,
+}
+
+#[derive(Default)]
+struct State {
+    shutting_down: bool,
+    next_id: u64,
+    connections: HashMap<u64, Tracked>,
+}
+
+struct Tracked {
+    stream: TcpStream,
+    idle: bool,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(local_addr: SocketAddr) -> Self {
+        // A listener bound to the unspecified address is reachable on loopback.
+        let mut wake_addr = local_addr;
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(match wake_addr.ip() {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State::default()),
+                closed: Condvar::new(),
+                wake_addr,
+            }),
+        }
+    }
+
+    /// Starts shutting the server down and returns without waiting for it to
+    /// finish. Calling it again has no effect.
+    pub fn shutdown(&self) {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            if state.shutting_down {
+                return;
+            }
+            state.shutting_down = true;
+            for tracked in state.connections.values().filter(|t| t.idle) {
+                // Wakes up the worker blocked reading the next request.
+                let _ = tracked.stream.shutdown(Shutdown::Read);
+            }
+        }
+        // The accept loop only notices the shutdown once it accepts something.
+        let _ = TcpStream::connect_timeout(&self.shared.wake_addr, Duration::from_secs(1));
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shared.state.lock().unwrap().shutting_down
+    }
+
+    /// Starts tracking an accepted connection, which stops being tracked when
+    /// the returned [`Connection`] is dropped.
+    pub(crate) fn track(&self, stream: TcpStream) -> io::Result<Connection> {
+        let tracked = Tracked {
+            stream: stream.try_clone()?,
+            idle: true,
+        };
+        let mut state = self.shared.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.connections.insert(id, tracked);
+        Ok(Connection {
+            stream,
+            id,
+            handle: self.clone(),
+        })
+    }
+
+    /// Waits up to `timeout` for all tracked connections to close, then
+    /// forcibly closes any that remain. Returns whether they all closed in
+    /// time.
+    pub(crate) fn drain(&self, timeout: Duration) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        let (state, result) = self
+            .shared
+            .closed
+            .wait_timeout_while(state, timeout, |state| !state.connections.is_empty())
+            .unwrap();
+        if !result.timed_out() {
+            return true;
+        }
+        for tracked in state.connections.values() {
+            let _ = tracked.stream.shutdown(Shutdown::Both);
+        }
+        false
+    }
+
+    fn set_idle(&self, id: u64, idle: bool) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(tracked) = state.connections.get_mut(&id) {
+            tracked.idle = idle;
+        }
+        !state.shutting_down
+    }
+}
+
+/// An accepted connection, tracked so that shutting down can close it.
+pub(crate) struct Connection {
+    pub stream: TcpStream,
+    id: u64,
+    handle: ShutdownHandle,
+}
+
+impl Connection {
+    /// Marks the connection as waiting for its next request, which a shutdown
+    /// may interrupt. Returns `false` if the server is shutting down.
+    pub fn idle(&self) -> bool {
+        self.handle.set_idle(self.id, true)
+    }
+
+    /// Marks the connection as serving a request, which a shutdown waits for.
+    /// Returns `false` if the server is shutting down.
+    pub fn busy(&self) -> bool {
+        self.handle.set_idle(self.id, false)
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.handle.is_shutting_down()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let mut state = self.handle.shared.state.lock().unwrap();
+        state.connections.remove(&self.id);
+        self.handle.shared.closed.notify_all();
+    }
+}
@@ -0,0 +1,41 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt::{self, Formatter},
+    sync::Arc,
+};
+
+/// Shared application state, holding at most one value of each type.
+///
+/// Values are registered on the [`Router`](crate::route::Router) at startup
+/// and attached to every request it routes, so handlers can read them through
+/// `request.state`. Cloning is cheap: the values themselves are shared.
+#[derive(Clone, Default)]
+pub struct State {
+    values: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores `value`, replacing any previous value of the same type.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.values.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("State")
+            .field("values", &self.values.len())
+            .finish()
+    }
+}
@@ -0,0 +1,215 @@
+//! End-to-end tests against a server listening on an ephemeral port.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpStream},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use codecrafters_http_server::{
+    config::Config, handlers, net::Server, route::Router, shutdown::ShutdownHandle,
+};
+
+struct TestServer {
+    addr: SocketAddr,
+    shutdown: ShutdownHandle,
+    thread: JoinHandle<io::Result<()>>,
+}
+
+impl TestServer {
+    fn start(config: Config) -> Self {
+        let mut router = Router::new();
+        router.add_route("GET", "/", handlers::root_handler);
+        router.add_route("GET", "/echo/:msg", handlers::echo_handler);
+        let config = Config {
+            port: 0,
+            workers: 2,
+            ..config
+        };
+
+        let server = Server::bind(router, &config).unwrap();
+        let addr = server.local_addr().unwrap();
+        let shutdown = server.shutdown_handle();
+        let thread = thread::spawn(move || server.run());
+        Self {
+            addr,
+            shutdown,
+            thread,
+        }
+    }
+
+    fn connect(&self) -> Client {
+        let stream = TcpStream::connect(self.addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        Client {
+            reader: BufReader::new(stream),
+        }
+    }
+
+    fn stop(self) {
+        self.shutdown.shutdown();
+        self.thread.join().unwrap().unwrap();
+    }
+}
+
+struct Client {
+    reader: BufReader<TcpStream>,
+}
+
+struct Reply {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl Client {
+    fn send(&mut self, request: &str) -> Reply {
+        self.reader.get_mut().write_all(request.as_bytes()).unwrap();
+        self.read_reply()
+    }
+
+    fn read_reply(&mut self) -> Reply {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        let status = line.split(' ').nth(1).unwrap().parse().unwrap();
+
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            self.reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').unwrap();
+            headers.push((name.to_string(), value.trim().to_string()));
+        }
+
+        let mut reply = Reply {
+            status,
+            headers,
+            body: Vec::new(),
+        };
+        let length = reply
+            .header("Content-Length")
+            .map_or(0, |v| v.parse().unwrap());
+        reply.body.resize(length, 0);
+        self.reader.read_exact(&mut reply.body).unwrap();
+        reply
+    }
+
+    /// Whether the server has closed the connection, with nothing left to
+    /// read.
+    fn is_closed(&mut self) -> bool {
+        let mut rest = Vec::new();
+        matches!(self.reader.read_to_end(&mut rest), Ok(0))
+    }
+}
+
+#[test]
+fn http_10_connections_close_by_default() {
+    let server = TestServer::start(Config::default());
+
+    let mut client = server.connect();
+    let reply = client.send("GET / HTTP/1.0\r\n\r\n");
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.header("Connection"), Some("close"));
+    assert!(client.is_closed());
+
+    server.stop();
+}
+
+#[test]
+fn http_10_connections_persist_on_request() {
+    let server = TestServer::start(Config::default());
+
+    let mut client = server.connect();
+    for msg in ["one", "two"] {
+        let reply = client.send(&format!(
+            "GET /echo/{msg} HTTP/1.0\r\nConnection: keep-alive\r\n\r\n"
+        ));
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.body, msg.as_bytes());
+        assert_eq!(reply.header("Connection"), Some("keep-alive"));
+        assert!(reply.header("Keep-Alive").is_some());
+    }
+
+    server.stop();
+}
+
+#[test]
+fn http_11_connections_persist_until_closed() {
+    let server = TestServer::start(Config::default());
+
+    let mut client = server.connect();
+    let reply = client.send("GET / HTTP/1.1\r\nHost: test\r\n\r\n");
+    assert_eq!(reply.header("Connection"), None);
+    let reply = client.send("GET / HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n");
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.header("Connection"), Some("close"));
+    assert!(client.is_closed());
+
+    server.stop();
+}
+
+#[test]
+fn closes_connections_after_max_requests() {
+    let server = TestServer::start(Config {
+        max_requests: 3,
+        keep_alive_timeout: Duration::from_secs(7),
+        ..Config::default()
+    });
+
+    let mut client = server.connect();
+    for max in [2, 1] {
+        let reply = client.send("GET / HTTP/1.1\r\nHost: test\r\n\r\n");
+        assert_eq!(reply.header("Connection"), None);
+        assert_eq!(
+            reply.header("Keep-Alive"),
+            Some(format!("timeout=7, max={max}").as_str())
+        );
+    }
+    let reply = client.send("GET / HTTP/1.1\r\nHost: test\r\n\r\n");
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.header("Connection"), Some("close"));
+    assert_eq!(reply.header("Keep-Alive"), None);
+    assert!(client.is_closed());
+
+    server.stop();
+}
+
+#[test]
+fn shutdown_closes_idle_connections() {
+    let server = TestServer::start(Config {
+        keep_alive_timeout: Duration::from_secs(60),
+        shutdown_timeout: Duration::from_secs(60),
+        ..Config::default()
+    });
+
+    let mut used = server.connect();
+    let reply = used.send("GET / HTTP/1.1\r\nHost: test\r\n\r\n");
+    assert_eq!(reply.status, 200);
+    let mut unused = server.connect();
+    // Give the server time to take the unused connection on.
+    thread::sleep(Duration::from_millis(100));
+
+    // Neither connection is serving a request, so neither holds up the
+    // shutdown for the keep-alive or shutdown timeouts.
+    let start = Instant::now();
+    server.stop();
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert!(used.is_closed());
+    assert!(unused.is_closed());
+}